@@ -14,6 +14,11 @@ pub enum Code {
     EnhanceYourCalm,
     InadequateSecurity,
     Http1Required,
+    /// An error code outside the range RFC 7540 §7 defines. Per that
+    /// section, unknown error codes MUST NOT be treated as errors, so this
+    /// carries the raw wire value through instead of making `from_h2_id`
+    /// panic on a peer using an extension or forward-compatible code.
+    Unknown(u32),
 }
 
 pub const ALL_ERRORS: [Code; 14] = [
@@ -34,12 +39,14 @@ pub const ALL_ERRORS: [Code; 14] = [
 ];
 
 impl Code {
-    pub fn from_h2_id(id: usize) -> Code {
-        assert!(id < ALL_ERRORS.len(), "id={}", id);
-        ALL_ERRORS[id].clone()
+    pub fn from_h2_id(id: u32) -> Code {
+        match ALL_ERRORS.get(id as usize) {
+            Some(code) => code.clone(),
+            None => Code::Unknown(id),
+        }
     }
 
-    pub fn to_h2_id(&self) -> usize {
+    pub fn to_h2_id(&self) -> u32 {
         match self {
             Code::NoError => 0,
             Code::ProtocolError => 1,
@@ -55,11 +62,12 @@ impl Code {
             Code::EnhanceYourCalm => 11,
             Code::InadequateSecurity => 12,
             Code::Http1Required => 13,
+            Code::Unknown(id) => *id,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Level {
     StreamLevel,
     ConnectionLevel,
@@ -71,6 +79,10 @@ pub struct Error {
     code: Code,
     message: String,
     cause: Option<tokio::io::Error>,
+    /// The stream a `StreamLevel` error is scoped to, so it can be answered
+    /// with a RST_STREAM instead of tearing down the whole connection. Always
+    /// `None` for a `ConnectionLevel` error. Set via `new_for_stream`.
+    stream_id: Option<u32>,
 }
 
 impl Error {
@@ -85,6 +97,7 @@ impl Error {
             code,
             message: desp,
             cause: None,
+            stream_id: None,
         }
     }
 
@@ -104,8 +117,39 @@ impl Error {
             code,
             message: desp,
             cause: Some(cause),
+            stream_id: None,
+        }
+    }
+
+    /// A `StreamLevel` error scoped to `stream_id`. See `stream_id()`.
+    pub fn new_for_stream(
+        code: Code,
+        stream_id: u32,
+        message: String) -> Error {
+        let desp = format!(
+            "Code: {:?}, on stream {}, with details \"{}\"", code, stream_id, message);
+        Error{
+            level: Level::StreamLevel,
+            code,
+            message: desp,
+            cause: None,
+            stream_id: Some(stream_id),
         }
     }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    /// `Some(stream_id)` for an error built with `new_for_stream`, `None`
+    /// otherwise (in particular, always `None` for `ConnectionLevel`).
+    pub fn stream_id(&self) -> Option<u32> {
+        self.stream_id
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -139,5 +183,17 @@ mod test {
             assert_eq!(trial, *oracle);
         }
     }
+
+    #[test]
+    fn errorcode_unknown_does_not_panic() {
+        let trial = Code::from_h2_id(ALL_ERRORS.len() as u32 + 42);
+        assert_eq!(trial, Code::Unknown(ALL_ERRORS.len() as u32 + 42));
+    }
+
+    #[test]
+    fn errorcode_unknown_roundtrips() {
+        let oracle = Code::Unknown(0xdead_beef);
+        assert_eq!(Code::from_h2_id(oracle.to_h2_id()), oracle);
+    }
 }
 
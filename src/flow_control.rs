@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::error;
+
+/// RFC 7540 §6.9.1: a flow-control window must never exceed 2^31-1.
+const MAX_WINDOW_SIZE: i64 = 0x7fff_ffff;
+
+/// Why a WINDOW_UPDATE was rejected, distinguishing the whole-connection case
+/// (stream id 0) from a single stream's: RFC 7540 §6.9.1 only requires the
+/// former to tear down the connection with a GOAWAY, while the latter only
+/// needs that one stream reset with RST_STREAM, leaving the rest of the
+/// connection alone. An overflowing `INITIAL_WINDOW_SIZE` shift is not
+/// reported through this type: `on_initial_window_size_change` returns a
+/// bare `error::Code` for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowUpdateError {
+    Connection(error::Code),
+    Stream(u32, error::Code),
+}
+
+/// Tracks connection-level and per-stream send *and* receive windows, seeded
+/// from `initial_window_size` (default 65535 per RFC 7540 §6.5.2) until each
+/// side's own `SETTINGS_INITIAL_WINDOW_SIZE` is known. Windows are signed
+/// because a SETTINGS change to `InitialWindowSize` can drive an existing
+/// stream's window negative (RFC 7540 §6.9.2); outgoing DATA then stays
+/// gated on that stream until enough WINDOW_UPDATEs bring it back above
+/// zero.
+///
+/// The send side (`send_initial_window_size`, `connection_window`,
+/// `stream_windows`) reflects the *peer's* advertised
+/// `SETTINGS_INITIAL_WINDOW_SIZE` — `on_initial_window_size_change` updates
+/// it whenever the peer's SETTINGS says so. The receive side
+/// (`recv_initial_window_size`, `connection_recv_window`,
+/// `stream_recv_windows`) reflects *our own* advertised value instead —
+/// `set_recv_initial_window_size` updates it when this end's own SETTINGS is
+/// configured. The two must stay independent: the peer shrinking its window
+/// says nothing about how much of their data we ourselves are willing to
+/// buffer per stream.
+pub struct FlowController {
+    send_initial_window_size: Mutex<i64>,
+    connection_window: Mutex<i64>,
+    stream_windows: Mutex<HashMap<u32, i64>>,
+    recv_initial_window_size: Mutex<i64>,
+    connection_recv_window: Mutex<i64>,
+    stream_recv_windows: Mutex<HashMap<u32, i64>>,
+}
+
+impl FlowController {
+    pub fn new(initial_window_size: u32) -> FlowController {
+        FlowController{
+            send_initial_window_size: Mutex::new(initial_window_size as i64),
+            connection_window: Mutex::new(initial_window_size as i64),
+            stream_windows: Mutex::new(HashMap::new()),
+            recv_initial_window_size: Mutex::new(initial_window_size as i64),
+            connection_recv_window: Mutex::new(initial_window_size as i64),
+            stream_recv_windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The number of bytes of DATA that may currently be sent on
+    /// `stream_id`, i.e. `min(connection_window, stream_window)`. Outgoing
+    /// DATA must be gated so it never exceeds this.
+    pub fn available(&self, stream_id: u32) -> i64 {
+        let conn = *self.connection_window.lock().unwrap();
+        let stream = self.stream_window_or_default(&self.stream_windows.lock().unwrap(), stream_id);
+        conn.min(stream)
+    }
+
+    /// The connection's and `stream_id`'s current receive windows
+    /// (`min(connection_recv_window, stream_recv_window)`), i.e. how much
+    /// more DATA may be received before a WINDOW_UPDATE is owed back to the
+    /// peer.
+    pub fn recv_available(&self, stream_id: u32) -> i64 {
+        let conn = *self.connection_recv_window.lock().unwrap();
+        let stream = self.stream_recv_available(stream_id);
+        conn.min(stream)
+    }
+
+    /// Decrements both the connection- and stream-level send windows by `n`,
+    /// as must happen immediately before `n` bytes of DATA are put on the
+    /// wire; the caller must have already checked `available(stream_id) >=
+    /// n` to refuse to emit when that would drive either window negative.
+    pub fn consume(&self, stream_id: u32, n: u32) {
+        *self.connection_window.lock().unwrap() -= n as i64;
+        let mut streams = self.stream_windows.lock().unwrap();
+        let initial = *self.send_initial_window_size.lock().unwrap();
+        let window = streams.entry(stream_id).or_insert(initial);
+        *window -= n as i64;
+    }
+
+    /// Decrements both the connection- and stream-level receive windows by
+    /// `n`, as happens when a DATA frame of that length is received. Unlike
+    /// `consume`, there is nothing to refuse here: the peer has already put
+    /// the bytes on the wire, so this only has to record that they arrived
+    /// (a replenishing WINDOW_UPDATE is this connection's job to send, not
+    /// covered here).
+    pub fn on_data_received(&self, stream_id: u32, n: u32) {
+        *self.connection_recv_window.lock().unwrap() -= n as i64;
+        let mut streams = self.stream_recv_windows.lock().unwrap();
+        let initial = *self.recv_initial_window_size.lock().unwrap();
+        let window = streams.entry(stream_id).or_insert(initial);
+        *window -= n as i64;
+    }
+
+    /// Applies an incoming WINDOW_UPDATE's increment to the relevant window
+    /// (the connection window if `stream_id == 0`, else that stream's
+    /// window), per RFC 7540 §6.9. A zero increment is a PROTOCOL_ERROR, and
+    /// any increment pushing the window above the 2^31-1 limit is a
+    /// FLOW_CONTROL_ERROR; both are reported as `WindowUpdateError::Connection`
+    /// for stream id 0 and `WindowUpdateError::Stream` otherwise, so the
+    /// caller knows whether the whole connection or just one stream must be
+    /// torn down.
+    pub fn on_window_update(&self, stream_id: u32, increment: u32) -> Result<(), WindowUpdateError> {
+        let wrap_err = |code| if stream_id == 0 {
+            WindowUpdateError::Connection(code)
+        } else {
+            WindowUpdateError::Stream(stream_id, code)
+        };
+
+        if increment == 0 {
+            return Err(wrap_err(error::Code::ProtocolError));
+        }
+
+        if stream_id == 0 {
+            let mut window = self.connection_window.lock().unwrap();
+            let updated = *window + increment as i64;
+            if updated > MAX_WINDOW_SIZE {
+                return Err(wrap_err(error::Code::FlowControlError));
+            }
+            *window = updated;
+        } else {
+            let mut streams = self.stream_windows.lock().unwrap();
+            let initial = *self.send_initial_window_size.lock().unwrap();
+            let window = streams.entry(stream_id).or_insert(initial);
+            let updated = *window + increment as i64;
+            if updated > MAX_WINDOW_SIZE {
+                return Err(wrap_err(error::Code::FlowControlError));
+            }
+            *window = updated;
+        }
+        Ok(())
+    }
+
+    /// When the peer's SETTINGS frame changes `SETTINGS_INITIAL_WINDOW_SIZE`,
+    /// every existing stream's send window must shift by the signed delta
+    /// `new_value - old_value` (RFC 7540 §6.9.2). Returns `FlowControlError`
+    /// without applying the change if doing so would push any stream's
+    /// window above the 2^31-1 limit.
+    pub fn on_initial_window_size_change(&self, new_value: u32) -> Result<(), error::Code> {
+        let mut initial = self.send_initial_window_size.lock().unwrap();
+        let delta = new_value as i64 - *initial;
+        let mut streams = self.stream_windows.lock().unwrap();
+        if streams.values().any(|window| window + delta > MAX_WINDOW_SIZE) {
+            return Err(error::Code::FlowControlError);
+        }
+        *initial = new_value as i64;
+        for window in streams.values_mut() {
+            *window += delta;
+        }
+        Ok(())
+    }
+
+    /// Sets the window new streams' *receive* side starts at, driven by this
+    /// end's own `SETTINGS_INITIAL_WINDOW_SIZE` rather than the peer's — see
+    /// the struct-level doc comment for why the two must not share a field.
+    /// Called once, when this end's outgoing SETTINGS is configured, so
+    /// unlike `on_initial_window_size_change` there is no existing-stream
+    /// delta to apply.
+    pub fn set_recv_initial_window_size(&self, new_value: u32) {
+        *self.recv_initial_window_size.lock().unwrap() = new_value as i64;
+    }
+
+    /// The connection-level receive window alone, ignoring any one stream's.
+    /// Used to decide when enough DATA has been consumed to owe the peer a
+    /// connection-level WINDOW_UPDATE.
+    pub fn connection_recv_available(&self) -> i64 {
+        *self.connection_recv_window.lock().unwrap()
+    }
+
+    /// Credits the connection-level receive window by `n`, as must happen
+    /// immediately before a connection-level WINDOW_UPDATE carrying that
+    /// increment is put on the wire.
+    pub fn replenish_connection_recv_window(&self, n: u32) {
+        *self.connection_recv_window.lock().unwrap() += n as i64;
+    }
+
+    /// The send window size new streams start at, i.e. the most recent
+    /// peer `SETTINGS_INITIAL_WINDOW_SIZE` applied via
+    /// `on_initial_window_size_change`.
+    pub fn send_initial_window_size(&self) -> i64 {
+        *self.send_initial_window_size.lock().unwrap()
+    }
+
+    /// The receive window size new streams start at, i.e. this end's own
+    /// `SETTINGS_INITIAL_WINDOW_SIZE` as set via `set_recv_initial_window_size`.
+    pub fn recv_initial_window_size(&self) -> i64 {
+        *self.recv_initial_window_size.lock().unwrap()
+    }
+
+    /// `stream_id`'s own receive window alone, ignoring the connection-level
+    /// one. Used to decide when enough DATA has been consumed on that one
+    /// stream to owe the peer a stream-level WINDOW_UPDATE.
+    pub fn stream_recv_available(&self, stream_id: u32) -> i64 {
+        self.stream_window_or(&self.stream_recv_windows.lock().unwrap(), stream_id, self.recv_initial_window_size())
+    }
+
+    /// Credits `stream_id`'s receive window by `n`, as must happen
+    /// immediately before a stream-level WINDOW_UPDATE carrying that
+    /// increment is put on the wire.
+    pub fn replenish_stream_recv_window(&self, stream_id: u32, n: u32) {
+        let mut streams = self.stream_recv_windows.lock().unwrap();
+        let initial = *self.recv_initial_window_size.lock().unwrap();
+        let window = streams.entry(stream_id).or_insert(initial);
+        *window += n as i64;
+    }
+
+    fn stream_window_or_default(&self, streams: &HashMap<u32, i64>, stream_id: u32) -> i64 {
+        self.stream_window_or(streams, stream_id, self.send_initial_window_size())
+    }
+
+    fn stream_window_or(&self, streams: &HashMap<u32, i64>, stream_id: u32, initial: i64) -> i64 {
+        match streams.get(&stream_id) {
+            Some(w) => *w,
+            None => initial,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_stream_starts_at_initial_window_size() {
+        let fc = FlowController::new(65535);
+        assert_eq!(fc.available(1), 65535);
+    }
+
+    #[test]
+    fn consume_decrements_both_windows() {
+        let fc = FlowController::new(1000);
+        fc.consume(1, 100);
+        assert_eq!(fc.available(1), 900);
+        // a second, untouched stream only sees the connection-level debit.
+        assert_eq!(fc.available(2), 900);
+    }
+
+    #[test]
+    fn window_update_adds_to_the_right_window() {
+        let fc = FlowController::new(1000);
+        fc.consume(1, 100);
+        // stream 1 is now 900, capped by an equally-depleted connection
+        // window; bumping only the stream window doesn't move the cap.
+        fc.on_window_update(1, 50).unwrap();
+        assert_eq!(fc.available(1), 900);
+        // bumping the connection window (stream_id 0) raises the cap for
+        // every stream, including ones that were never touched.
+        fc.on_window_update(0, 50).unwrap();
+        assert_eq!(fc.available(1), 950);
+        assert_eq!(fc.available(2), 950);
+    }
+
+    #[test]
+    fn window_update_overflow_is_a_flow_control_error() {
+        let fc = FlowController::new(0x7fff_ffff);
+        assert_eq!(fc.on_window_update(1, 1), Err(WindowUpdateError::Stream(1, error::Code::FlowControlError)));
+        assert_eq!(fc.on_window_update(0, 1), Err(WindowUpdateError::Connection(error::Code::FlowControlError)));
+    }
+
+    #[test]
+    fn window_update_zero_increment_is_a_protocol_error() {
+        let fc = FlowController::new(1000);
+        assert_eq!(fc.on_window_update(1, 0), Err(WindowUpdateError::Stream(1, error::Code::ProtocolError)));
+        assert_eq!(fc.on_window_update(0, 0), Err(WindowUpdateError::Connection(error::Code::ProtocolError)));
+    }
+
+    #[test]
+    fn data_received_decrements_both_recv_windows() {
+        let fc = FlowController::new(1000);
+        fc.on_data_received(1, 100);
+        assert_eq!(fc.recv_available(1), 900);
+        // a second, untouched stream only sees the connection-level debit.
+        assert_eq!(fc.recv_available(2), 900);
+        // receive windows are independent of send windows.
+        assert_eq!(fc.available(1), 1000);
+    }
+
+    #[test]
+    fn initial_window_size_change_shifts_existing_streams_by_the_delta() {
+        let fc = FlowController::new(65535);
+        fc.consume(1, 35535); // stream 1 now at 30000
+        fc.on_initial_window_size_change(100).unwrap();
+        // delta = 100 - 65535 = -65435; 30000 - 65435 = -35435
+        assert_eq!(fc.available(1), -35435);
+        // a brand-new stream picks up the new initial value directly.
+        assert_eq!(fc.available(2), 100);
+    }
+
+    #[test]
+    fn connection_recv_window_can_be_replenished_independently_of_streams() {
+        let fc = FlowController::new(1000);
+        fc.on_data_received(1, 400);
+        assert_eq!(fc.connection_recv_available(), 600);
+        fc.replenish_connection_recv_window(400);
+        assert_eq!(fc.connection_recv_available(), 1000);
+        // replenishing the connection window doesn't touch the stream one.
+        assert_eq!(fc.recv_available(1), 600);
+    }
+
+    #[test]
+    fn initial_window_size_change_overflow_is_rejected_without_mutating_state() {
+        let fc = FlowController::new(100);
+        // raise the connection window out of the way so `available` below
+        // reflects the stream window, not a connection-level cap.
+        fc.on_window_update(0, 0x7fff_ffff - 100).unwrap();
+        fc.on_window_update(1, 0x7fff_ffff - 100).unwrap(); // stream 1 now at the limit
+        assert_eq!(fc.on_initial_window_size_change(101), Err(error::Code::FlowControlError));
+        // the rejected change must not have been partially applied.
+        assert_eq!(fc.available(1), 0x7fff_ffff);
+        assert_eq!(fc.available(2), 100);
+    }
+
+    #[test]
+    fn stream_recv_window_can_be_replenished_independently_of_connection() {
+        let fc = FlowController::new(1000);
+        fc.on_data_received(1, 400);
+        assert_eq!(fc.stream_recv_available(1), 600);
+        fc.replenish_stream_recv_window(1, 400);
+        assert_eq!(fc.stream_recv_available(1), 1000);
+        // replenishing the stream window doesn't touch the connection one.
+        assert_eq!(fc.connection_recv_available(), 600);
+    }
+
+    #[test]
+    fn recv_initial_window_size_is_independent_of_send_side() {
+        let fc = FlowController::new(65535);
+        // the peer shrinking its advertised (send-side) window...
+        fc.on_initial_window_size_change(100).unwrap();
+        assert_eq!(fc.send_initial_window_size(), 100);
+        // ...must not affect how much of our own receive window a brand-new
+        // stream starts with.
+        assert_eq!(fc.recv_initial_window_size(), 65535);
+        assert_eq!(fc.recv_available(2), 65535);
+        // likewise, setting our own advertised receive-side initial window...
+        fc.set_recv_initial_window_size(5000);
+        assert_eq!(fc.recv_available(3), 5000);
+        // ...must not affect the peer's send-side window.
+        assert_eq!(fc.send_initial_window_size(), 100);
+    }
+}
@@ -35,36 +35,116 @@ impl FrameHeader {
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Frame {
+    Data(DataFrame), // 0
     Headers(ReceivedHeadersFrame), // 1
     Priority(PriorityFrame), // 2
+    RstStream(RstStreamFrame), // 3
     Settings(SettingsFrame), // 4
+    Ping(PingFrame), // 6
     GoAway(GoAwayFrame), // 7
+    WindowUpdate(WindowUpdateFrame), // 8
 }
 
 impl Frame {
+    /// Parses one raw frame. A HEADERS (or CONTINUATION) frame whose block
+    /// doesn't end with END_HEADERS doesn't produce a frame to dispatch to
+    /// the caller yet: its bytes are stashed on `conn.pending_header_block`
+    /// and a placeholder `Frame::Headers` with `end_headers: false` is
+    /// returned, which the receive loop recognizes and skips dispatching
+    /// (see `net::receive_coroutine_continuation`). While a block is
+    /// pending, RFC 7540 §6.2 requires every other frame on the connection
+    /// to be rejected until the CONTINUATION(s) that finish it arrive.
     pub fn parse(
         conn: &Arc<Connection>,
         header: &FrameHeader,
         body: Vec<u8>,
     ) -> Result<Frame, Error> {
+        {
+            let pending = conn.pending_header_block.lock().unwrap();
+            if let Some(ref p) = *pending {
+                if header.frame_type != 9 || header.stream_id != p.stream_id {
+                    return Err(Error::new(
+                        error::Level::ConnectionLevel,
+                        error::Code::ProtocolError,
+                        "expected a CONTINUATION frame on the same stream to finish an in-progress header block".to_string()));
+                }
+            }
+        }
         match header.frame_type {
+            0 => {
+                let f = DataFrame::parse(header, body)?;
+                Ok(Frame::Data(f))
+            },
             1 => {
-                let mut decoder = conn.as_ref().header_decoder.lock().unwrap();
-                let f = ReceivedHeadersFrame::parse(&mut decoder, header, body)?;
-                Ok(Frame::Headers(f))
+                let raw = RawHeaderFragment::parse_headers(header, body)?;
+                if (header.flags & 0x4) > 0 {
+                    let mut decoder = conn.as_ref().header_decoder.lock().unwrap();
+                    let header_block = decode_header_block(&mut decoder, &raw.block_fragment)?;
+                    Ok(Frame::Headers(ReceivedHeadersFrame{
+                        stream_id: raw.stream_id,
+                        end_stream: raw.end_stream,
+                        end_headers: true,
+                        header_block,
+                        padding: raw.padding,
+                        priority: raw.priority,
+                    }))
+                } else {
+                    let f = raw.pending_frame();
+                    *conn.pending_header_block.lock().unwrap() = Some(raw);
+                    Ok(Frame::Headers(f))
+                }
             },
             2 => {
                 let f = PriorityFrame::parse(header, body)?;
                 Ok(Frame::Priority(f))
             }
+            3 => {
+                let f = RstStreamFrame::parse(header, body)?;
+                Ok(Frame::RstStream(f))
+            }
             4 => {
                 let f = SettingsFrame::parse(header, body)?;
                 Ok(Frame::Settings(f))
             },
+            6 => {
+                let f = PingFrame::parse(header, body)?;
+                Ok(Frame::Ping(f))
+            },
             7 => {
                 let f = GoAwayFrame::parse(header, body)?;
                 Ok(Frame::GoAway(f))
             }
+            8 => {
+                let f = WindowUpdateFrame::parse(header, body)?;
+                Ok(Frame::WindowUpdate(f))
+            }
+            9 => {
+                let pending = conn.pending_header_block.lock().unwrap().take();
+                let mut pending = match pending {
+                    Some(p) => p,
+                    None => return Err(Error::new(
+                        error::Level::ConnectionLevel,
+                        error::Code::ProtocolError,
+                        "CONTINUATION frame without a preceding HEADERS frame".to_string())),
+                };
+                pending.block_fragment.extend_from_slice(&body);
+                if (header.flags & 0x4) > 0 {
+                    let mut decoder = conn.as_ref().header_decoder.lock().unwrap();
+                    let header_block = decode_header_block(&mut decoder, &pending.block_fragment)?;
+                    Ok(Frame::Headers(ReceivedHeadersFrame{
+                        stream_id: pending.stream_id,
+                        end_stream: pending.end_stream,
+                        end_headers: true,
+                        header_block,
+                        padding: pending.padding,
+                        priority: pending.priority,
+                    }))
+                } else {
+                    let f = pending.pending_frame();
+                    *conn.pending_header_block.lock().unwrap() = Some(pending);
+                    Ok(Frame::Headers(f))
+                }
+            },
             _ => Err(Error::new(
                 error::Level::ConnectionLevel,
                 error::Code::ProtocolError,
@@ -74,8 +154,29 @@ impl Frame {
 
     pub fn serialize(&self) -> Vec<u8> {
         match self {
+            Frame::Data(f) => f.serialize(),
             Frame::Settings(f) => f.serialize(),
+            Frame::Ping(f) => f.serialize(),
             Frame::GoAway(f) => f.serialize(),
+            Frame::RstStream(f) => f.serialize(),
+            Frame::WindowUpdate(f) => f.serialize(),
+            _ => panic!("unknown frame type: {:?}", self)
+        }
+    }
+
+    /// Like `serialize`, but returns an ordered list of byte segments (frame
+    /// header, then payload chunks) instead of one concatenated buffer, so a
+    /// caller can hand them to a vectored write and skip the concatenation
+    /// copy. Settings/GoAway frames are small enough that there is nothing
+    /// to split, so this is a single-element list for them.
+    pub fn serialize_segments(&self) -> Vec<Vec<u8>> {
+        match self {
+            Frame::Data(f) => f.serialize_segments(),
+            Frame::Settings(f) => vec![f.serialize()],
+            Frame::Ping(f) => vec![f.serialize()],
+            Frame::GoAway(f) => vec![f.serialize()],
+            Frame::RstStream(f) => vec![f.serialize()],
+            Frame::WindowUpdate(f) => vec![f.serialize()],
             _ => panic!("unknown frame type: {:?}", self)
         }
     }
@@ -83,6 +184,8 @@ impl Frame {
 
 #[derive(Debug)]
 pub enum SendFrame {
+    Data(DataFrame), // 0
+    RstStream(RstStreamFrame), // 3
     Settings(SettingsFrame), // 4
     GoAway(GoAwayFrame), // 7
 }
@@ -90,66 +193,173 @@ pub enum SendFrame {
 impl SendFrame {
     pub fn serialize(&self, conn: &Arc<Connection>) -> Vec<u8> {
         match self {
+            SendFrame::Data(f) => f.serialize(),
+            SendFrame::RstStream(f) => f.serialize(),
             SendFrame::Settings(f) => f.serialize(),
             SendFrame::GoAway(f) => f.serialize(),
             _ => panic!("unknown frame type: {:?}", self)
         }
     }
+
+    /// See `Frame::serialize_segments`.
+    pub fn serialize_segments(&self, _conn: &Arc<Connection>) -> Vec<Vec<u8>> {
+        match self {
+            SendFrame::Data(f) => f.serialize_segments(),
+            SendFrame::RstStream(f) => vec![f.serialize()],
+            SendFrame::Settings(f) => vec![f.serialize()],
+            SendFrame::GoAway(f) => vec![f.serialize()],
+            _ => panic!("unknown frame type: {:?}", self)
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
-pub struct ReceivedHeadersFrame {
+pub struct DataFrame {
     pub stream_id: u32,
     pub end_stream: bool,
-    pub end_headers: bool,
-    pub header_block: Vec<DecoderField>,
+    pub data: Vec<u8>,
     pub padding: Option<Vec<u8>>,
-    pub priority: Option<PriorityInHeadersFrame>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub struct PriorityInHeadersFrame {
-    weight: u8,
-    dependency_stream: u32,
-}
+impl DataFrame {
+    pub fn new(
+        stream_id: u32,
+        end_stream: bool,
+        data: Vec<u8>,
+        padding: Option<Vec<u8>>,
+    ) -> DataFrame {
+        DataFrame{stream_id, end_stream, data, padding}
+    }
 
-impl ReceivedHeadersFrame {
     fn parse(
-        decoder: &mut hpack::Decoder,
         header: &FrameHeader,
         body: Vec<u8>,
-    ) -> Result<ReceivedHeadersFrame, Error> {
+    ) -> Result<DataFrame, Error> {
+        assert!(header.frame_type == 0);
+
         if header.stream_id == 0 {
             return Err(Error::new(
                 error::Level::ConnectionLevel,
                 error::Code::ProtocolError,
-                "ReceivedHeadersFrame associates with stream 0.".to_string()));
+                "a DATA frame must be associated with a stream.".to_string()));
         }
 
-        let mut frame = ReceivedHeadersFrame{
+        let padded = (header.flags & 0x8) > 0;
+        let mut body: &[u8] = body.as_slice();
+
+        let mut pad_len = 0usize;
+        if padded {
+            let (buf, len) = parse_uint::<u8>(body, 1);
+            body = buf;
+            pad_len = len as usize;
+        }
+
+        if pad_len > body.len() {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::ProtocolError,
+                "Too long padding.".to_string()));
+        }
+
+        let (data, padding) = body.split_at(body.len() - pad_len);
+        Ok(DataFrame{
             stream_id: header.stream_id,
-            end_stream: false,
-            end_headers: false,
-            header_block: vec!(),
-            padding: None,
-            priority: None,
-        };
+            end_stream: (header.flags & 0x1) > 0,
+            data: data.to_vec(),
+            padding: if padded {Some(padding.to_vec())} else {None},
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_segments().concat()
+    }
 
-        if (header.flags & 0x1) > 0 {
-            frame.end_stream = true;
+    /// See `Frame::serialize_segments`: keeps `data` as its own segment so a
+    /// vectored write can send a (potentially large) DATA payload straight
+    /// out of this `Vec` without concatenating it onto the frame header.
+    fn serialize_segments(&self) -> Vec<Vec<u8>> {
+        let mut header_buf = vec!();
+        let mut body_len = self.data.len();
+        if let Some(p) = &self.padding {
+            body_len += p.len() + 1;
         }
-        if (header.flags & 0x4) > 0 {
-            frame.end_headers = true;
+        let h = FrameHeader{
+            body_len,
+            frame_type: 0,
+            flags: {
+                let mut flags = 0u8;
+                if self.end_stream {
+                    flags |= 0x1;
+                }
+                if self.padding.is_some() {
+                    flags |= 0x8;
+                }
+                flags
+            },
+            stream_id: self.stream_id,
+        };
+        h.serialize(&mut header_buf);
+        if let Some(p) = &self.padding {
+            serialize_uint(&mut header_buf, p.len() as u64, 1);
         }
-        let mut padded = false;
-        if (header.flags & 0x8) > 0 {
-            padded = true;
+
+        let mut segments = vec![header_buf, self.data.clone()];
+        if let Some(p) = &self.padding {
+            segments.push(p.clone());
         }
-        let mut prioritized = false;
-        if (header.flags & 0x20) > 0 {
-            prioritized = true;
+        segments
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ReceivedHeadersFrame {
+    pub stream_id: u32,
+    pub end_stream: bool,
+    pub end_headers: bool,
+    pub header_block: Vec<HeaderField>,
+    pub padding: Option<Vec<u8>>,
+    pub priority: Option<PriorityInHeadersFrame>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PriorityInHeadersFrame {
+    /// The dependency weight, 1..=256 (the wire byte, 0..=255, plus one per
+    /// RFC 7540 §5.3.2).
+    pub weight: u16,
+    pub exclusive: bool,
+    pub dependency_stream: u32,
+}
+
+/// A HEADERS (or CONTINUATION) frame's payload with the header block left
+/// HPACK-encoded: stream id, END_STREAM, padding and priority are already
+/// parsed out, but the block fragment itself hasn't been run through the
+/// decoder yet. HPACK decoding is stateful and order-sensitive, so a block
+/// split across HEADERS + CONTINUATION frames (RFC 7540 §6.2, §6.10) can't
+/// be decoded fragment-by-fragment; the connection buffers one of these
+/// (`Connection::pending_header_block`) until a CONTINUATION with
+/// END_HEADERS supplies the rest.
+pub struct RawHeaderFragment {
+    stream_id: u32,
+    end_stream: bool,
+    padding: Option<Vec<u8>>,
+    priority: Option<PriorityInHeadersFrame>,
+    block_fragment: Vec<u8>,
+}
+
+impl RawHeaderFragment {
+    /// Parses a HEADERS frame's stream id, END_STREAM, padding and priority,
+    /// leaving the header block itself undecoded in `block_fragment`.
+    fn parse_headers(header: &FrameHeader, body: Vec<u8>) -> Result<RawHeaderFragment, Error> {
+        if header.stream_id == 0 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::ProtocolError,
+                "ReceivedHeadersFrame associates with stream 0.".to_string()));
         }
 
+        let padded = (header.flags & 0x8) > 0;
+        let prioritized = (header.flags & 0x20) > 0;
+
         let mut body: &[u8] = body.as_slice();
 
         let mut pad_len = 0usize;
@@ -159,13 +369,15 @@ impl ReceivedHeadersFrame {
             pad_len = len as usize;
         }
 
+        let mut priority = None;
         if prioritized {
-            let (buf, sid) = parse_uint::<u32>(body, 4);
+            let (buf, raw_dependency) = parse_uint::<u32>(body, 4);
             let (buf, weight) = parse_uint::<u8>(buf, 1);
             body = buf;
-            frame.priority = Some(PriorityInHeadersFrame{
-                weight,
-                dependency_stream: sid});
+            priority = Some(PriorityInHeadersFrame{
+                weight: weight as u16 + 1,
+                exclusive: (raw_dependency & 0x8000_0000) != 0,
+                dependency_stream: raw_dependency & 0x7fff_ffff});
         }
 
         if pad_len > body.len() {
@@ -175,32 +387,75 @@ impl ReceivedHeadersFrame {
                 "Too long padding.".to_string()));
         }
 
-        {
-            let (head, tail) = body.split_at(body.len() - pad_len);
-            {
-                let mut input: &[u8] = head;
-                while !input.is_empty() {
-                    match decoder.parse_header_field(input) {
-                        Ok((remain, result)) => {
-                            frame.header_block.push(result);
-                            input = remain;
-                        },
-                        Err(err) => {
-                            return Err(Error::new(
-                                error::Level::ConnectionLevel,
-                                error::Code::CompressionError,
-                                err.to_string(),
-                            ));
-                        }
-                    }
-                }
-            }
-            if padded {
-                frame.padding = Some(tail.to_vec());
-            }
+        let (block_fragment, tail) = body.split_at(body.len() - pad_len);
+        Ok(RawHeaderFragment{
+            stream_id: header.stream_id,
+            end_stream: (header.flags & 0x1) > 0,
+            padding: if padded {Some(tail.to_vec())} else {None},
+            priority,
+            block_fragment: block_fragment.to_vec(),
+        })
+    }
+
+    /// A `Frame::Headers` with an empty `header_block` and `end_headers:
+    /// false`, handed back to the caller as a no-op placeholder while this
+    /// fragment's block is still waiting on more CONTINUATION frames.
+    fn pending_frame(&self) -> ReceivedHeadersFrame {
+        ReceivedHeadersFrame{
+            stream_id: self.stream_id,
+            end_stream: self.end_stream,
+            end_headers: false,
+            header_block: vec!(),
+            padding: self.padding.clone(),
+            priority: self.priority.clone(),
         }
-        
-        Ok(frame)
+    }
+}
+
+/// Runs a complete, concatenated HPACK-encoded header block through
+/// `decoder` via `Decoder::decode_block`, translating its error into this
+/// connection's own `Error` type.
+fn decode_header_block(decoder: &mut hpack::Decoder, block: &[u8]) -> Result<Vec<HeaderField>, Error> {
+    decoder.decode_block(block).map_err(|err| Error::new(
+        error::Level::ConnectionLevel,
+        error::Code::CompressionError,
+        err.to_string(),
+    ))
+}
+
+impl ReceivedHeadersFrame {
+    fn parse(
+        decoder: &mut hpack::Decoder,
+        header: &FrameHeader,
+        body: Vec<u8>,
+    ) -> Result<ReceivedHeadersFrame, Error> {
+        let raw = RawHeaderFragment::parse_headers(header, body)?;
+        let header_block = decode_header_block(decoder, &raw.block_fragment)?;
+        Ok(ReceivedHeadersFrame{
+            stream_id: raw.stream_id,
+            end_stream: raw.end_stream,
+            end_headers: (header.flags & 0x4) > 0,
+            header_block,
+            padding: raw.padding,
+            priority: raw.priority,
+        })
+    }
+
+    /// Interprets `header_block` as a request, splitting out its
+    /// pseudo-headers and enforcing RFC 7540 §8.1.2.1's malformed-message
+    /// rules. `connect_protocol_enabled` should reflect whether this
+    /// connection has negotiated RFC 8441's `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    /// with the peer, which gates whether a `:protocol` pseudo-header is
+    /// legal.
+    pub fn as_request(&self, connect_protocol_enabled: bool) -> Result<Request, Error> {
+        Request::from_header_block(&self.header_block, self.stream_id, connect_protocol_enabled)
+    }
+
+    /// Interprets `header_block` as a response, splitting out its
+    /// `:status` pseudo-header and enforcing RFC 7540 §8.1.2.1's
+    /// malformed-message rules.
+    pub fn as_response(&self) -> Result<Response, Error> {
+        Response::from_header_block(&self.header_block, self.stream_id)
     }
 
 }
@@ -210,7 +465,7 @@ pub struct SendHeadersFrame {
     stream_id: u32,
     end_stream: bool,
     end_headers: bool,
-    headers: Vec<EncoderField>,
+    headers: Vec<(CacheHint, AnySliceable, AnySliceable)>,
     padding: Option<Vec<u8>>,
     priority: Option<PriorityInHeadersFrame>,
 }
@@ -231,15 +486,39 @@ impl SendHeadersFrame {
         }
     }
 
-    fn serialize(&self, encoder: &mut hpack::Encoder) -> Vec<u8> {
+    fn serialize(&self, encoder: &mut hpack::Encoder, max_frame_size: u32) -> Vec<u8> {
+        self.serialize_segments(encoder, max_frame_size).concat()
+    }
+
+    /// Like `serialize`, but keeps the (often large) HPACK-encoded header
+    /// block as its own segment instead of copying it onto the end of the
+    /// frame-header buffer, so a vectored write can send it straight out of
+    /// this `Vec` without a further concatenation copy.
+    ///
+    /// If the encoded header block is larger than `max_frame_size` (the
+    /// peer's `SETTINGS_MAX_FRAME_SIZE`), it is fragmented: a leading HEADERS
+    /// frame carries the first chunk with END_HEADERS cleared, followed by
+    /// one or more CONTINUATION frames, with END_HEADERS set only on the
+    /// final fragment. Padding and the priority fields stay on the HEADERS
+    /// frame only, per RFC 7540 §6.2; END_STREAM stays there too regardless
+    /// of how the block is split. Because the caller enqueues and writes
+    /// these segments as a single unit (see `net::write_all_vectored`), no
+    /// other frame can land on the wire in between without extra locking.
+    fn serialize_segments(&self, encoder: &mut hpack::Encoder, max_frame_size: u32) -> Vec<Vec<u8>> {
         let mut header_buf = vec!();
-        for field in &self.headers {
-            encoder.encode_header_field(&mut header_buf, field);
-        }
+        encoder.encode_block(&mut header_buf, self.headers.iter()
+            .map(|(hint, name, value)| (*hint, name.as_slice(), value.as_slice())));
+
+        let chunk_size = (max_frame_size as usize).max(1);
+        let chunks: Vec<&[u8]> = if header_buf.is_empty() {
+            vec![&header_buf[..]]
+        } else {
+            header_buf.chunks(chunk_size).collect()
+        };
 
-        let mut main_buf = vec!();
+        let mut frame_header_buf = vec!();
         let mut header = FrameHeader{
-            body_len: header_buf.len(),
+            body_len: chunks[0].len(),
             frame_type: 1,
             flags: 0,
             stream_id: self.stream_id,
@@ -248,7 +527,7 @@ impl SendHeadersFrame {
         if self.end_stream {
             header.flags |= 0x1;
         }
-        if self.end_headers {
+        if chunks.len() == 1 && self.end_headers {
             header.flags |= 0x4;
         }
         if self.padding.is_some() {
@@ -259,23 +538,38 @@ impl SendHeadersFrame {
             header.flags |= 0x20;
             header.body_len += 5;
         }
-        header.serialize(&mut main_buf);
+        header.serialize(&mut frame_header_buf);
         if self.padding.is_some() {
             let p = self.padding.as_ref().unwrap();
-            serialize_uint(&mut main_buf, p.len() as u64, 1);
+            serialize_uint(&mut frame_header_buf, p.len() as u64, 1);
         }
         if self.priority.is_some() {
             let p = self.priority.as_ref().unwrap();
-            serialize_uint(&mut main_buf, p.dependency_stream, 4);
-            serialize_uint(&mut main_buf, p.weight, 1);
+            let raw_dependency = p.dependency_stream | if p.exclusive {0x8000_0000} else {0};
+            serialize_uint(&mut frame_header_buf, raw_dependency, 4);
+            serialize_uint(&mut frame_header_buf, (p.weight - 1) as u8 as u32, 1);
         }
-        main_buf.append(&mut header_buf);
-        if self.padding.is_some() {
-            let p = self.padding.as_ref().unwrap();
-            main_buf.extend_from_slice(p.as_slice());
+
+        let mut segments = vec![frame_header_buf, chunks[0].to_vec()];
+        if let Some(p) = &self.padding {
+            segments.push(p.clone());
+        }
+
+        for (i, chunk) in chunks.iter().enumerate().skip(1) {
+            let is_last = i == chunks.len() - 1;
+            let mut continuation_header_buf = vec!();
+            let h = FrameHeader{
+                body_len: chunk.len(),
+                frame_type: 9,
+                flags: if is_last && self.end_headers {0x4} else {0},
+                stream_id: self.stream_id,
+            };
+            h.serialize(&mut continuation_header_buf);
+            segments.push(continuation_header_buf);
+            segments.push(chunk.to_vec());
         }
 
-        main_buf
+        segments
     }
 }
 
@@ -284,7 +578,7 @@ pub struct SendHeadersFrameBuilder {
     stream_id: Option<u32>,
     end_stream: bool,
     end_headers: bool,
-    headers: Vec<EncoderField>,
+    headers: Vec<(CacheHint, AnySliceable, AnySliceable)>,
     padding: Option<Vec<u8>>,
     priority: Option<PriorityInHeadersFrame>,
 }
@@ -316,8 +610,8 @@ impl SendHeadersFrameBuilder {
         self
     }
 
-    pub fn append_header_field(&mut self, field: EncoderField) -> &mut Self {
-        self.headers.push(field);
+    pub fn append_header_field(&mut self, hint: CacheHint, name: AnySliceable, value: AnySliceable) -> &mut Self {
+        self.headers.push((hint, name, value));
         self
     }
 
@@ -334,20 +628,25 @@ impl SendHeadersFrameBuilder {
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct PriorityFrame {
-    my_stream_id: u32,
-    dep_stream_id: u32,
-    weight: i64,
+    pub my_stream_id: u32,
+    pub dep_stream_id: u32,
+    pub exclusive: bool,
+    /// The dependency weight, 1..=256 (the wire byte, 0..=255, plus one per
+    /// RFC 7540 §5.3.2).
+    pub weight: u16,
 }
 
 impl PriorityFrame {
     pub fn new(
         my_stream_id: u32,
         dep_stream_id: u32,
-        weight: i64
+        exclusive: bool,
+        weight: u16,
     ) -> PriorityFrame {
         PriorityFrame{
             my_stream_id,
             dep_stream_id,
+            exclusive,
             weight,
         }
     }
@@ -364,20 +663,84 @@ impl PriorityFrame {
         }
 
         if body.len() != 5 {
-            return Err(Error::new(
-                error::Level::StreamLevel,
+            return Err(Error::new_for_stream(
                 error::Code::FrameSizeError,
+                header.stream_id,
                 "PriorityFrame must has a body of length 5.".to_string()));
         }
 
         let body: &[u8] = body.as_slice();
-        let (body, dep_stream_id) = parse_uint::<u32>(body, 4);
+        let (body, raw_dependency) = parse_uint::<u32>(body, 4);
         let (_, weight) = parse_uint::<u8>(body, 1);
 
         Ok(PriorityFrame::new(
             header.stream_id,
-            dep_stream_id,
-            weight as i64))
+            raw_dependency & 0x7fff_ffff,
+            (raw_dependency & 0x8000_0000) != 0,
+            weight as u16 + 1))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct RstStreamFrame {
+    pub stream_id: u32,
+    pub error_code: error::Code,
+}
+
+impl RstStreamFrame {
+    pub fn new(stream_id: u32, error_code: error::Code) -> RstStreamFrame {
+        RstStreamFrame{stream_id, error_code}
+    }
+
+    /// Builds the RST_STREAM that answers a stream-scoped `err` in place of
+    /// tearing down the whole connection with GOAWAY. Returns `None` for a
+    /// connection-level error (`err.stream_id()` is `None`), which the
+    /// caller should instead answer with GOAWAY as before.
+    pub fn from_stream_error(err: &Error) -> Option<RstStreamFrame> {
+        err.stream_id().map(|stream_id| RstStreamFrame::new(stream_id, err.code().clone()))
+    }
+
+    fn parse(
+        header: &FrameHeader,
+        body: Vec<u8>,
+    ) -> Result<RstStreamFrame, Error> {
+        assert!(header.frame_type == 3);
+
+        if header.stream_id == 0 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::ProtocolError,
+                "a RST_STREAM frame must be associated with a stream.".to_string()));
+        }
+
+        if body.len() != 4 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::FrameSizeError,
+                "a RST_STREAM frame must have a body of length 4.".to_string()));
+        }
+
+        let (_, ec) = parse_uint::<u32>(body.as_slice(), 4);
+        Ok(RstStreamFrame{
+            stream_id: header.stream_id,
+            error_code: error::Code::from_h2_id(ec),
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec!();
+
+        {
+            let h = FrameHeader{
+                body_len: 4,
+                frame_type: 3,
+                flags: 0,
+                stream_id: self.stream_id};
+            h.serialize(&mut buf);
+        }
+        serialize_uint(&mut buf, self.error_code.to_h2_id(), 4);
+
+        buf
     }
 }
 
@@ -385,6 +748,12 @@ impl PriorityFrame {
 pub struct SettingsFrame {
     pub ack: bool,
     pub values: Vec<(SettingKey, u32)>,
+    /// Raw (identifier, value) pairs whose identifier isn't one of the six
+    /// RFC 7540 §6.5.2 settings this tree knows about. Per that section, a
+    /// conformant endpoint MUST ignore an unknown identifier rather than
+    /// rejecting the frame over it, but it's still free to echo them back,
+    /// so they're preserved here instead of being dropped on parse.
+    pub unknown: Vec<(u16, u32)>,
 }
 
 impl SettingsFrame {
@@ -392,9 +761,9 @@ impl SettingsFrame {
         ack: bool,
         values: Vec<(SettingKey, u32)>,
     ) -> SettingsFrame {
-        SettingsFrame{ack, values}
+        SettingsFrame{ack, values, unknown: vec!()}
     }
-    
+
     fn parse(
         header: &FrameHeader,
         body: Vec<u8>,
@@ -414,15 +783,24 @@ impl SettingsFrame {
                 error::Code::ProtocolError,
                 "body length of a SETTINGS frame must be a multiple of 6 octets.".to_string()));
         }
-        
+
+        let ack = header.flags & 0x1 > 0;
+        if ack && !body.is_empty() {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::FrameSizeError,
+                "a SETTINGS frame with the ACK flag set must have an empty body.".to_string()));
+        }
+
         let mut settings = SettingsFrame{
-            ack: false,
+            ack,
             values: vec!(),
+            unknown: vec!(),
         };
 
-        if header.flags & 0x1 > 0 {
-            settings.ack = true;
-        }
+        // reused purely to borrow its `apply` validation; never installed as
+        // this connection's actual settings.
+        let mut scratch = Settings::new();
 
         let mut body: &[u8] = body.as_slice();
         while body.len() > 0 {
@@ -430,7 +808,16 @@ impl SettingsFrame {
             let (buf, value) = parse_uint::<u32>(buf, 4);
 
             if identifier >= 1 && identifier <= 6 {
-                settings.values.push((SettingKey::from_h2_id(identifier as usize), value));
+                let key = SettingKey::from_h2_id(identifier as usize);
+                if let Err(err) = scratch.apply(key.clone(), value) {
+                    return Err(Error::new(
+                        error::Level::ConnectionLevel,
+                        err.code.clone(),
+                        format!("{}", err)));
+                }
+                settings.values.push((key, value));
+            } else {
+                settings.unknown.push((identifier, value));
             }
 
             body = buf;
@@ -444,7 +831,7 @@ impl SettingsFrame {
 
         {
             let h = FrameHeader{
-                body_len: 6 * self.values.len(),
+                body_len: 6 * (self.values.len() + self.unknown.len()),
                 frame_type: 4u8,
                 flags: if self.ack {1u8} else {0u8},
                 stream_id: 0u32};
@@ -454,7 +841,11 @@ impl SettingsFrame {
             serialize_uint(&mut buf, k.to_h2_id() as u32, 2);
             serialize_uint(&mut buf, *v, 4);
         }
-        
+        for (id, v) in &self.unknown {
+            serialize_uint(&mut buf, *id as u32, 2);
+            serialize_uint(&mut buf, *v, 4);
+        }
+
         buf
     }
 }
@@ -491,7 +882,7 @@ impl GoAwayFrame {
         {
             let (buf, last_stream_id) = parse_uint::<u32>(body.as_slice(), 4);
             frame.last_stream_id = last_stream_id;
-            let (buf, ec) = parse_uint::<usize>(buf, 4);
+            let (buf, ec) = parse_uint::<u32>(buf, 4);
             frame.error_code = error::Code::from_h2_id(ec);
             frame.debug_info = buf.to_vec();
         }
@@ -510,13 +901,113 @@ impl GoAwayFrame {
             h.serialize(&mut buf);
         }
         serialize_uint(&mut buf, self.last_stream_id, 4);
-        serialize_uint(&mut buf, self.error_code.to_h2_id() as u32, 4);
+        serialize_uint(&mut buf, self.error_code.to_h2_id(), 4);
         buf.extend(self.debug_info.iter());
         
         buf
     }
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub struct WindowUpdateFrame {
+    pub stream_id: u32,
+    pub increment: u32,
+}
+
+impl WindowUpdateFrame {
+    pub fn new(stream_id: u32, increment: u32) -> WindowUpdateFrame {
+        WindowUpdateFrame{stream_id, increment}
+    }
+
+    fn parse(
+        header: &FrameHeader,
+        body: Vec<u8>,
+    ) -> Result<WindowUpdateFrame, Error> {
+        assert!(header.frame_type == 8);
+
+        if body.len() != 4 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::FrameSizeError,
+                "a WINDOW_UPDATE frame must have a body of length 4.".to_string()));
+        }
+
+        // the high bit of the increment is reserved and must be ignored.
+        let (_, raw) = parse_uint::<u32>(body.as_slice(), 4);
+        Ok(WindowUpdateFrame{
+            stream_id: header.stream_id,
+            increment: raw & 0x7fff_ffff})
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec!();
+
+        {
+            let h = FrameHeader{
+                body_len: 4,
+                frame_type: 8,
+                flags: 0,
+                stream_id: self.stream_id};
+            h.serialize(&mut buf);
+        }
+        serialize_uint(&mut buf, self.increment, 4);
+
+        buf
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct PingFrame {
+    pub opaque: u64,
+    pub ack: bool,
+}
+
+impl PingFrame {
+    pub fn new(opaque: u64, ack: bool) -> PingFrame {
+        PingFrame{opaque, ack}
+    }
+
+    fn parse(
+        header: &FrameHeader,
+        body: Vec<u8>,
+    ) -> Result<PingFrame, Error> {
+        assert!(header.frame_type == 6);
+
+        if header.stream_id != 0 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::ProtocolError,
+                "a PING frame can only be applied to the whole connection.".to_string()));
+        }
+
+        if body.len() != 8 {
+            return Err(Error::new(
+                error::Level::ConnectionLevel,
+                error::Code::FrameSizeError,
+                "a PING frame must have a body of length 8.".to_string()));
+        }
+
+        let (_, opaque) = parse_uint::<u64>(body.as_slice(), 8);
+        Ok(PingFrame{opaque, ack: header.flags & 0x1 > 0})
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec!();
+
+        {
+            let h = FrameHeader{
+                body_len: 8,
+                frame_type: 6,
+                flags: if self.ack {1u8} else {0u8},
+                stream_id: 0};
+            h.serialize(&mut buf);
+        }
+        serialize_uint(&mut buf, self.opaque, 8);
+
+        buf
+    }
+}
+
 #[cfg(test)]
 mod test {
     use random::Source;
@@ -526,17 +1017,41 @@ mod test {
     fn settingsframe_serde() {
         let mut rng = random::default();
         for _ in 0..1000 {
-            let ack = if (rng.read_u64() & 1) > 0 {true} else {false};
+            // an ACK must carry an empty body, so only build up values/unknown
+            // pairs when this round isn't one.
+            let ack = (rng.read_u64() & 1) > 0;
             let mut values = vec!();
-            loop {
-                let rnd = (rng.read_u64() as usize) % (ALL_SETTING_KEYS.len() + 1);
-                if rnd == 0 {
-                    break;
+            let mut unknown = vec!();
+            if !ack {
+                loop {
+                    let rnd = (rng.read_u64() as usize) % (ALL_SETTING_KEYS.len() + 2);
+                    if rnd == 0 {
+                        break;
+                    } else if rnd <= ALL_SETTING_KEYS.len() {
+                        let key = SettingKey::from_h2_id(rnd);
+                        // keep every value within the range `parse` accepts
+                        // for its key, so the round trip isn't rejected.
+                        let value = match key {
+                            SettingKey::EnablePush => (rng.read_u64() & 1) as u32,
+                            SettingKey::InitialWindowSize => rng.read_u64() as u32 & 0x7fff_ffff,
+                            SettingKey::MaxFrameSize =>
+                                16384 + (rng.read_u64() as u32 % (16_777_215 - 16384 + 1)),
+                            _ => rng.read_u64() as u32,
+                        };
+                        values.push((key, value));
+                    } else {
+                        // an identifier outside the six this tree recognizes,
+                        // which must round-trip unmodified rather than being
+                        // dropped (RFC 7540 §6.5.2).
+                        unknown.push((
+                            ALL_SETTING_KEYS.len() as u16 + 1 + (rng.read_u64() as u16 % 1000),
+                            rng.read_u64() as u32));
+                    }
                 }
-                values.push((SettingKey::from_h2_id(rnd), 0x12345678u32));
             }
 
-            let f_oracle = SettingsFrame::new(ack, values);
+            let mut f_oracle = SettingsFrame::new(ack, values);
+            f_oracle.unknown = unknown;
             let mut buf = f_oracle.serialize();
             let header = FrameHeader::parse(&buf[0..9]);
             let buf = buf.split_off(9);
@@ -548,6 +1063,57 @@ mod test {
         }
     }
 
+    #[test]
+    fn settingsframe_parse_rejects_invalid_enable_push() {
+        let mut body = vec!();
+        serialize_uint(&mut body, SettingKey::EnablePush.to_h2_id() as u32, 2);
+        serialize_uint(&mut body, 2u32, 4);
+        let header = FrameHeader{body_len: body.len(), frame_type: 4, flags: 0, stream_id: 0};
+        let err = SettingsFrame::parse(&header, body).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn settingsframe_parse_rejects_oversized_initial_window_size() {
+        let mut body = vec!();
+        serialize_uint(&mut body, SettingKey::InitialWindowSize.to_h2_id() as u32, 2);
+        serialize_uint(&mut body, 0x8000_0000u32, 4);
+        let header = FrameHeader{body_len: body.len(), frame_type: 4, flags: 0, stream_id: 0};
+        let err = SettingsFrame::parse(&header, body).unwrap_err();
+        assert!(format!("{:?}", err).contains("FlowControlError"), "{:?}", err);
+    }
+
+    #[test]
+    fn settingsframe_parse_rejects_out_of_range_max_frame_size() {
+        let mut body = vec!();
+        serialize_uint(&mut body, SettingKey::MaxFrameSize.to_h2_id() as u32, 2);
+        serialize_uint(&mut body, 100u32, 4);
+        let header = FrameHeader{body_len: body.len(), frame_type: 4, flags: 0, stream_id: 0};
+        let err = SettingsFrame::parse(&header, body).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn settingsframe_parse_rejects_ack_with_nonempty_body() {
+        let mut body = vec!();
+        serialize_uint(&mut body, SettingKey::MaxConcurrentStreams.to_h2_id() as u32, 2);
+        serialize_uint(&mut body, 10u32, 4);
+        let header = FrameHeader{body_len: body.len(), frame_type: 4, flags: 0x1, stream_id: 0};
+        let err = SettingsFrame::parse(&header, body).unwrap_err();
+        assert!(format!("{:?}", err).contains("FrameSizeError"), "{:?}", err);
+    }
+
+    #[test]
+    fn settingsframe_parse_preserves_unknown_identifiers() {
+        let mut body = vec!();
+        serialize_uint(&mut body, 0xbeefu32, 2);
+        serialize_uint(&mut body, 0xdead_beefu32, 4);
+        let header = FrameHeader{body_len: body.len(), frame_type: 4, flags: 0, stream_id: 0};
+        let f = SettingsFrame::parse(&header, body).unwrap();
+        assert_eq!(f.values, vec!());
+        assert_eq!(f.unknown, vec![(0xbeefu16, 0xdead_beefu32)]);
+    }
+
     fn randomized_vec<T: Eq + Clone>(alphabet: &[T], terminator: T) -> Vec<T> {
         let mut rng = random::default();
         let len = alphabet.len();
@@ -562,13 +1128,48 @@ mod test {
         out
     }
 
+    #[test]
+    fn dataframe_serde() {
+        let mut rng = random::default();
+        for _ in 0..1000 {
+            let padding = randomized_vec(b"abcde.", b'.');
+            let f_oracle = DataFrame::new(
+                // stream id 0 is reserved for the whole connection and
+                // rejected by `DataFrame::parse`.
+                (rng.read_u64() as u32).saturating_add(1),
+                (rng.read_u64() & 1) > 0,
+                randomized_vec(b"abcdefghijklmn.", b'.'),
+                if padding.is_empty() {None} else {Some(padding)});
+
+            let mut buf = f_oracle.serialize();
+            let header = FrameHeader::parse(&buf[0..9]);
+            let buf = buf.split_off(9);
+            let f_trial = DataFrame::parse(&header, buf);
+            match f_trial {
+                Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+                Err(err) => assert!(false, "{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn dataframe_serialize_segments_matches_serialize() {
+        let f_oracle = DataFrame::new(1, true, b"hello world".to_vec(), Some(b"pad".to_vec()));
+        let segments = f_oracle.serialize_segments();
+        assert!(segments.len() > 1, "{:?}", segments);
+        assert_eq!(segments.concat(), f_oracle.serialize());
+    }
+
     #[test]
     fn goawayframe_serde() {
         let mut rng = random::default();
         for _ in 0..1000 {
             let mut f_oracle = GoAwayFrame::new();
             f_oracle.last_stream_id = rng.read_u64() as u32;
-            f_oracle.error_code = error::Code::from_h2_id((rng.read_u64() as usize) % ALL_ERRORS.len());
+            // occasionally exercise an out-of-range (extension) error code,
+            // which must round-trip as `Code::Unknown` rather than panic.
+            f_oracle.error_code = error::Code::from_h2_id(
+                rng.read_u64() as u32 % (ALL_ERRORS.len() as u32 + 5));
             f_oracle.debug_info = randomized_vec(b"abcdefghijklmn.", b'.');
 
             let mut buf = f_oracle.serialize();
@@ -582,6 +1183,171 @@ mod test {
         }
     }
 
+    #[test]
+    fn windowupdateframe_serde() {
+        let mut rng = random::default();
+        for _ in 0..1000 {
+            // the top bit is reserved and must round-trip as 0.
+            let f_oracle = WindowUpdateFrame::new(
+                rng.read_u64() as u32,
+                rng.read_u64() as u32 & 0x7fff_ffff);
+
+            let mut buf = f_oracle.serialize();
+            let header = FrameHeader::parse(&buf[0..9]);
+            let buf = buf.split_off(9);
+            let f_trial = WindowUpdateFrame::parse(&header, buf);
+            match f_trial {
+                Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+                Err(err) => assert!(false, "{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn frame_windowupdate_serialize_and_serialize_segments_round_trip_via_frame_parse() {
+        let f_oracle = Frame::WindowUpdate(WindowUpdateFrame::new(7, 100));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel::<Frame>(1);
+        let conn = Connection::new(Role::Server, |_conn, _frame| {}, tx);
+
+        let mut buf = f_oracle.serialize();
+        let header = FrameHeader::parse(&buf[0..9]);
+        let body = buf.split_off(9);
+        match Frame::parse(&conn, &header, body) {
+            Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let segments = f_oracle.serialize_segments();
+        assert_eq!(segments.len(), 1);
+        let header = FrameHeader::parse(&segments[0][0..9]);
+        let body = segments[0][9..].to_vec();
+        match Frame::parse(&conn, &header, body) {
+            Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn priorityframe_serde() {
+        let mut rng = random::default();
+        for _ in 0..1000 {
+            let f_oracle = PriorityFrame::new(
+                (rng.read_u64() as u32).saturating_add(1),
+                rng.read_u64() as u32 & 0x7fff_ffff,
+                (rng.read_u64() & 1) > 0,
+                rng.read_u64() as u8 as u16 + 1);
+
+            let mut buf = vec!();
+            let raw_dependency = f_oracle.dep_stream_id
+                | if f_oracle.exclusive {0x8000_0000} else {0};
+            let h = FrameHeader{body_len: 5, frame_type: 2, flags: 0, stream_id: f_oracle.my_stream_id};
+            h.serialize(&mut buf);
+            serialize_uint(&mut buf, raw_dependency, 4);
+            serialize_uint(&mut buf, (f_oracle.weight - 1) as u8 as u32, 1);
+
+            let header = FrameHeader::parse(&buf[0..9]);
+            let buf = buf.split_off(9);
+            let f_trial = PriorityFrame::parse(&header, buf);
+            match f_trial {
+                Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+                Err(err) => assert!(false, "{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn priorityframe_parse_masks_off_the_exclusive_bit() {
+        let mut buf = vec!();
+        serialize_uint(&mut buf, 0x8000_0002u32, 4);
+        serialize_uint(&mut buf, 9u32, 1);
+        let header = FrameHeader{body_len: 5, frame_type: 2, flags: 0, stream_id: 1};
+        let f = PriorityFrame::parse(&header, buf).unwrap();
+        assert_eq!(f.dep_stream_id, 2);
+        assert!(f.exclusive);
+        assert_eq!(f.weight, 10);
+    }
+
+    #[test]
+    fn rststreamframe_serde() {
+        let mut rng = random::default();
+        for _ in 0..1000 {
+            // occasionally exercise an out-of-range (extension) error code,
+            // which must round-trip as `Code::Unknown` rather than panic.
+            let f_oracle = RstStreamFrame::new(
+                (rng.read_u64() as u32).saturating_add(1),
+                error::Code::from_h2_id(rng.read_u64() as u32 % (ALL_ERRORS.len() as u32 + 5)));
+
+            let mut buf = f_oracle.serialize();
+            let header = FrameHeader::parse(&buf[0..9]);
+            let buf = buf.split_off(9);
+            let f_trial = RstStreamFrame::parse(&header, buf);
+            match f_trial {
+                Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+                Err(err) => assert!(false, "{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn rststreamframe_parse_rejects_stream_id_zero() {
+        let header = FrameHeader{body_len: 4, frame_type: 3, flags: 0, stream_id: 0};
+        let err = RstStreamFrame::parse(&header, vec![0u8; 4]).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn rststreamframe_parse_rejects_wrong_body_length() {
+        let header = FrameHeader{body_len: 3, frame_type: 3, flags: 0, stream_id: 1};
+        let err = RstStreamFrame::parse(&header, vec![0u8; 3]).unwrap_err();
+        assert!(format!("{:?}", err).contains("FrameSizeError"), "{:?}", err);
+    }
+
+    #[test]
+    fn rststreamframe_from_stream_error_converts_stream_level_errors_only() {
+        let stream_err = Error::new_for_stream(
+            error::Code::FrameSizeError, 3, "bad frame".to_string());
+        let rst = RstStreamFrame::from_stream_error(&stream_err);
+        assert_eq!(rst, Some(RstStreamFrame::new(3, error::Code::FrameSizeError)));
+
+        let conn_err = Error::new(
+            error::Level::ConnectionLevel,
+            error::Code::ProtocolError,
+            "bad connection".to_string());
+        assert_eq!(RstStreamFrame::from_stream_error(&conn_err), None);
+    }
+
+    #[test]
+    fn pingframe_serde() {
+        let mut rng = random::default();
+        for _ in 0..1000 {
+            let f_oracle = PingFrame::new(rng.read_u64(), (rng.read_u64() & 1) > 0);
+
+            let mut buf = f_oracle.serialize();
+            let header = FrameHeader::parse(&buf[0..9]);
+            let buf = buf.split_off(9);
+            let f_trial = PingFrame::parse(&header, buf);
+            match f_trial {
+                Ok(f_trial) => assert_eq!(f_trial, f_oracle),
+                Err(err) => assert!(false, "{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn pingframe_parse_rejects_nonzero_stream_id() {
+        let header = FrameHeader{body_len: 8, frame_type: 6, flags: 0, stream_id: 1};
+        let err = PingFrame::parse(&header, vec![0u8; 8]).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn pingframe_parse_rejects_wrong_body_length() {
+        let header = FrameHeader{body_len: 7, frame_type: 6, flags: 0, stream_id: 0};
+        let err = PingFrame::parse(&header, vec![0u8; 7]).unwrap_err();
+        assert!(format!("{:?}", err).contains("FrameSizeError"), "{:?}", err);
+    }
+
     #[test]
     fn headersframe_serde() {
         let mut rng = random::default();
@@ -601,22 +1367,13 @@ mod test {
                     let t = rng.read_u64() % 3;
                     let name = randomized_vec(b"abcdefghijklmn.", b'.');
                     let value = randomized_vec(b"abcdefghijklmn.", b'.');
-                    let field = match t {
-                        0 => EncoderField::ToCache((
-                            AnySliceable::new(name),
-                            AnySliceable::new(value),
-                        )),
-                        1 => EncoderField::NotCache((
-                            AnySliceable::new(name),
-                            AnySliceable::new(value),
-                        )),
-                        2 => EncoderField::NeverCache((
-                            AnySliceable::new(name),
-                            AnySliceable::new(value),
-                        )),
+                    let hint = match t {
+                        0 => CacheHint::PREFER_CACHE,
+                        1 => CacheHint::PREFER_NOT_CACHE,
+                        2 => CacheHint::NEVER_CACHE,
                         _ => unreachable!(),
                     };
-                    builder.append_header_field(field);
+                    builder.append_header_field(hint, AnySliceable::new(name), AnySliceable::new(value));
                 };
                 let padding = randomized_vec(b"abcde.", b'.');
                 if !padding.is_empty() {
@@ -624,14 +1381,15 @@ mod test {
                 }
                 if rng.read_u64() % 2 == 1 {
                     builder.set_priority(PriorityInHeadersFrame{
-                        weight: rng.read_u64() as u8,
-                        dependency_stream: rng.read_u64() as u32,
+                        weight: rng.read_u64() as u8 as u16 + 1,
+                        exclusive: (rng.read_u64() & 1) > 0,
+                        dependency_stream: rng.read_u64() as u32 & 0x7fff_ffff,
                     });
                 }
                 builder
             });
             println!("{} {:?}", i, f_oracle);
-            let mut buf = f_oracle.serialize(&mut encoder);
+            let mut buf = f_oracle.serialize(&mut encoder, 16384);
 
             let header = FrameHeader::parse(&buf[0..9]);
             let buf = buf.split_off(9);
@@ -651,37 +1409,36 @@ mod test {
                     assert_eq!(f_oracle.headers.len(), f_trial.header_block.len(),
                         "{:?} {:?}", f_oracle, f_trial);
                     for i in 0..f_oracle.headers.len() {
-                        let field_oracle = &f_oracle.headers[i];
+                        let (o_hint, o_name, o_value) = &f_oracle.headers[i];
                         let field_trial = &f_trial.header_block[i];
-                        match field_oracle {
-                            EncoderField::ToCache((o_name, o_value)) => {
+                        match o_hint {
+                            CacheHint::PREFER_CACHE => {
                                 match field_trial {
-                                    DecoderField::Normal((t_name, t_value)) => {
+                                    HeaderField::Index((t_name, t_value)) => {
                                         assert_eq!(o_name.as_slice(), t_name.as_slice());
                                         assert_eq!(o_value.as_slice(), t_value.as_slice());
                                     },
                                     _ => panic!(),
                                 }
                             },
-                            EncoderField::NotCache((o_name, o_value)) => {
+                            CacheHint::PREFER_NOT_CACHE => {
                                 match field_trial {
-                                    DecoderField::Normal((t_name, t_value)) => {
+                                    HeaderField::NotIndex((t_name, t_value)) => {
                                         assert_eq!(o_name.as_slice(), t_name.as_slice());
                                         assert_eq!(o_value.as_slice(), t_value.as_slice());
                                     },
                                     _ => panic!(),
                                 }
                             },
-                            EncoderField::NeverCache((o_name, o_value)) => {
+                            CacheHint::NEVER_CACHE => {
                                 match field_trial {
-                                    DecoderField::NeverIndex((t_name, t_value, _)) => {
+                                    HeaderField::NeverIndex((t_name, t_value, _)) => {
                                         assert_eq!(o_name.as_slice(), t_name.as_slice());
                                         assert_eq!(o_value.as_slice(), t_value.as_slice());
                                     },
                                     _ => panic!(),
                                 }
                             },
-                            _ => unreachable!(),
                         }
                     }
                 },
@@ -689,4 +1446,269 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn headersframe_serialize_segments_matches_serialize() {
+        let mut encoder = hpack::Encoder::with_capacity(100);
+        let f_oracle = SendHeadersFrame::new({
+            let mut builder = SendHeadersFrameBuilder::new();
+            builder.set_stream_id(1);
+            builder.set_end_headers();
+            builder.set_end_stream();
+            builder.append_header_field(CacheHint::PREFER_CACHE,
+                AnySliceable::new(b":status".to_vec()),
+                AnySliceable::new(b"200".to_vec()));
+            builder.set_padding(b"pad".to_vec());
+            builder
+        });
+        let segments = f_oracle.serialize_segments(&mut encoder, 16384);
+        assert!(segments.len() > 1, "{:?}", segments);
+        assert_eq!(segments.concat(), f_oracle.serialize(&mut encoder, 16384));
+    }
+
+    #[test]
+    fn headersframe_fragments_into_continuation_when_over_max_frame_size() {
+        let mut encoder = hpack::Encoder::with_capacity(100);
+        let f_oracle = SendHeadersFrame::new({
+            let mut builder = SendHeadersFrameBuilder::new();
+            builder.set_stream_id(1);
+            builder.set_end_headers();
+            builder.set_end_stream();
+            builder.append_header_field(CacheHint::NEVER_CACHE,
+                AnySliceable::new(b"x-custom-header".to_vec()),
+                AnySliceable::new(vec![b'a'; 100]));
+            builder
+        });
+        // force a split even though the header block here is tiny.
+        let max_frame_size = 10;
+        let segments = f_oracle.serialize_segments(&mut encoder, max_frame_size);
+
+        // [HEADERS header][HEADERS body][CONTINUATION header][CONTINUATION body]...
+        assert!(segments.len() >= 4, "{:?}", segments);
+        assert_eq!(segments.len() % 2, 0, "{:?}", segments);
+
+        let headers_header = FrameHeader::parse(&segments[0]);
+        assert_eq!(headers_header.frame_type, 1);
+        assert_eq!(headers_header.flags & 0x4, 0, "END_HEADERS must be cleared on the leading HEADERS frame");
+        assert_eq!(headers_header.flags & 0x1, 0x1, "END_STREAM must stay on the HEADERS frame");
+        assert!(segments[1].len() as u32 <= max_frame_size);
+
+        let continuation_count = (segments.len() - 2) / 2;
+        for i in 0..continuation_count {
+            let header_segment = &segments[2 + i * 2];
+            let body_segment = &segments[2 + i * 2 + 1];
+            let header = FrameHeader::parse(header_segment);
+            assert_eq!(header.frame_type, 9);
+            assert!(!body_segment.is_empty(), "no CONTINUATION fragment may be empty");
+            let is_last = i == continuation_count - 1;
+            assert_eq!(header.flags & 0x4, if is_last {0x4} else {0},
+                "END_HEADERS must be set only on the final fragment");
+        }
+    }
+
+    #[test]
+    fn frame_parse_reassembles_continuation_fragments() {
+        let mut encoder = hpack::Encoder::with_capacity(100);
+        let f_oracle = SendHeadersFrame::new({
+            let mut builder = SendHeadersFrameBuilder::new();
+            builder.set_stream_id(1);
+            builder.set_end_headers();
+            builder.append_header_field(CacheHint::PREFER_NOT_CACHE,
+                AnySliceable::new(b"x-custom-header".to_vec()),
+                AnySliceable::new(vec![b'a'; 100]));
+            builder
+        });
+        // force a split even though the header block here is tiny.
+        let segments = f_oracle.serialize_segments(&mut encoder, 10);
+        let pair_count = segments.len() / 2;
+        assert!(pair_count >= 2, "{:?}", segments);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel::<Frame>(1);
+        let conn = Connection::new(Role::Server, |_conn, _frame| {}, tx);
+
+        // every fragment but the last is a no-op placeholder: the block
+        // isn't finished, so there's nothing to decode yet.
+        for i in 0..pair_count - 1 {
+            let header = FrameHeader::parse(&segments[i * 2]);
+            let body = segments[i * 2 + 1].clone();
+            match Frame::parse(&conn, &header, body) {
+                Ok(Frame::Headers(ref f)) => assert!(!f.end_headers,
+                    "fragment {} should not complete the block yet", i),
+                other => panic!("unexpected result for fragment {}: {:?}", i, other),
+            }
+        }
+
+        // RFC 7540 §6.2: any frame other than the CONTINUATION that finishes
+        // the block is a connection error while one is pending.
+        let interleaved = FrameHeader{body_len: 0, frame_type: 0, flags: 0, stream_id: 1};
+        assert!(Frame::parse(&conn, &interleaved, vec!()).is_err());
+
+        let last_header = FrameHeader::parse(&segments[(pair_count - 1) * 2]);
+        let last_body = segments[(pair_count - 1) * 2 + 1].clone();
+        match Frame::parse(&conn, &last_header, last_body) {
+            Ok(Frame::Headers(f)) => {
+                assert!(f.end_headers);
+                assert_eq!(f.stream_id, 1);
+                assert_eq!(f.header_block.len(), 1);
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_parse_rejects_continuation_without_a_preceding_headers_frame() {
+        let (tx, _rx) = tokio::sync::mpsc::channel::<Frame>(1);
+        let conn = Connection::new(Role::Server, |_conn, _frame| {}, tx);
+        let header = FrameHeader{body_len: 0, frame_type: 9, flags: 0x4, stream_id: 1};
+        assert!(Frame::parse(&conn, &header, vec!()).is_err());
+    }
+
+    fn parse_single_headers_frame(fields: Vec<(CacheHint, AnySliceable, AnySliceable)>) -> ReceivedHeadersFrame {
+        let mut encoder = hpack::Encoder::with_capacity(100);
+        let f_oracle = SendHeadersFrame::new({
+            let mut builder = SendHeadersFrameBuilder::new();
+            builder.set_stream_id(1);
+            builder.set_end_headers();
+            builder.set_end_stream();
+            for (hint, name, value) in fields {
+                builder.append_header_field(hint, name, value);
+            }
+            builder
+        });
+        let mut buf = f_oracle.serialize(&mut encoder, 16384);
+        let header = FrameHeader::parse(&buf[0..9]);
+        let body = buf.split_off(9);
+        let (tx, _rx) = tokio::sync::mpsc::channel::<Frame>(1);
+        let conn = Connection::new(Role::Server, |_conn, _frame| {}, tx);
+        match Frame::parse(&conn, &header, body) {
+            Ok(Frame::Headers(f)) => f,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn not_cached(name: &[u8], value: &[u8]) -> (CacheHint, AnySliceable, AnySliceable) {
+        (CacheHint::PREFER_NOT_CACHE, AnySliceable::new(name.to_vec()), AnySliceable::new(value.to_vec()))
+    }
+
+    #[test]
+    fn headersframe_as_request_splits_pseudo_and_regular_headers() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b":authority", b"example.com"),
+            not_cached(b"accept", b"*/*"),
+        ]);
+        let req = f.as_request(false).unwrap();
+        assert_eq!(req.method, b"GET");
+        assert_eq!(req.scheme, b"https");
+        assert_eq!(req.path, b"/");
+        assert_eq!(req.authority, Some(b"example.com".to_vec()));
+        assert_eq!(req.protocol, None);
+        assert_eq!(req.headers, vec![(b"accept".to_vec(), b"*/*".to_vec())]);
+    }
+
+    #[test]
+    fn headersframe_as_response_extracts_status() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":status", b"200"),
+            not_cached(b"content-type", b"text/plain"),
+        ]);
+        let resp = f.as_response().unwrap();
+        assert_eq!(resp.status, b"200");
+        assert_eq!(resp.headers, vec![(b"content-type".to_vec(), b"text/plain".to_vec())]);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_pseudo_after_regular_header() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b"accept", b"*/*"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_duplicated_pseudo() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":method", b"POST"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_unknown_pseudo_header() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b":bogus", b"x"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_missing_required_pseudo() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_connection_specific_header() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b"transfer-encoding", b"chunked"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_te_other_than_trailers() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b"te", b"gzip"),
+        ]);
+        let err = f.as_request(false).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn headersframe_as_request_allows_te_trailers() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"GET"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b"te", b"trailers"),
+        ]);
+        assert!(f.as_request(false).is_ok());
+    }
+
+    #[test]
+    fn headersframe_as_request_rejects_protocol_pseudo_without_connect_protocol_enabled() {
+        let f = parse_single_headers_frame(vec![
+            not_cached(b":method", b"CONNECT"),
+            not_cached(b":scheme", b"https"),
+            not_cached(b":path", b"/"),
+            not_cached(b":protocol", b"websocket"),
+        ]);
+        assert!(f.as_request(false).is_err());
+        assert!(f.as_request(true).is_ok());
+    }
 }
@@ -0,0 +1,44 @@
+/// A reusable scratch buffer for decode-time byte copies.
+///
+/// A single `Decoder` can process thousands of header fields per
+/// connection; left unamortized, every Huffman-coded string literal would
+/// allocate (and free) its own `Vec<u8>` just to hold the decoded bytes
+/// before they are copied into their final `SelfOwnedSlice`. An `Arena`
+/// holds that intermediate buffer once and lets the decoder reuse its
+/// capacity across fields instead of reallocating it each time.
+pub struct Arena {
+    scratch: Vec<u8>,
+}
+
+impl Arena {
+    pub fn new(cap: usize) -> Arena {
+        Arena{scratch: Vec::with_capacity(cap)}
+    }
+
+    pub(crate) fn buf(&mut self) -> &mut Vec<u8> {
+        &mut self.scratch
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buf_reuses_capacity_across_calls() {
+        let mut arena = Arena::new(4);
+        {
+            let buf = arena.buf();
+            buf.clear();
+            buf.extend_from_slice(b"hello");
+        }
+        let cap = arena.buf().capacity();
+        {
+            let buf = arena.buf();
+            buf.clear();
+            buf.extend_from_slice(b"hi");
+            assert_eq!(buf.as_slice(), b"hi");
+        }
+        assert_eq!(arena.buf().capacity(), cap);
+    }
+}
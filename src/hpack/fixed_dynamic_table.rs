@@ -0,0 +1,256 @@
+// A const-generic, heap-free variant of `DynamicTable` for embedded/real-time
+// servers that cannot tolerate allocator jitter: header-field bytes live in a
+// `[u8; CAP]` owned directly by the table instead of a chain of heap-backed
+// `CacheBlock`s, and `CAP` also serves as the HPACK size limit (there is no
+// `set_max_size`, since the backing storage can't grow or shrink).
+//
+// Entries are appended newest-first into a ring over `buffer`, and evicted
+// oldest-first by `make_room`. To avoid ever splitting an entry's bytes
+// across the end of `buffer`, a wrap that would otherwise straddle the end
+// wastes the unused tail bytes instead; the wasted amount is folded into the
+// evicted entry's `waste_after` so `tail` stays in lock-step with physical
+// occupancy.
+//
+// `entries` is a second, independently-sized ring over `MAX_ENTRIES` slots
+// rather than `CAP` of them: RFC 7541 §4.1 charges every entry at least 32
+// bytes of `h2_used_size` regardless of its name/value length, so `CAP / 32`
+// is already an upper bound on how many entries can ever be live at once —
+// sizing `entries` to `CAP` (one slot per *byte* of capacity) wastes
+// `(CAP - CAP/32) * size_of::<Option<Entry>>()` for nothing. Stable Rust
+// can't derive `MAX_ENTRIES` from `CAP` itself (array lengths can't be const
+// generic expressions without the unstable `generic_const_exprs` feature),
+// so callers pick both; anything at least `CAP / 32 + 1` is a safe bound.
+
+pub struct DynamicTable<const CAP: usize, const MAX_ENTRIES: usize> {
+    buffer: [u8; CAP],
+    head: usize,
+    tail: usize,
+    h2_used_size: usize,
+    entries: [Option<Entry>; MAX_ENTRIES],
+    entries_start: usize,
+    entries_count: usize,
+    next_seq_id: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    name_start: usize,
+    name_len: usize,
+    value_len: usize,
+    waste_after: usize,
+}
+
+impl Entry {
+    fn phys_len(&self) -> usize {
+        self.name_len + self.value_len
+    }
+
+    fn h2_size(&self) -> usize {
+        self.name_len + self.value_len + 32
+    }
+}
+
+pub struct Item<'a> {
+    pub name: &'a [u8],
+    pub value: Option<&'a [u8]>,
+    pub index: usize,
+}
+
+#[derive(Debug)]
+enum MakeRoomResult {
+    NoRoom,
+    Enough,
+}
+
+impl<const CAP: usize, const MAX_ENTRIES: usize> DynamicTable<CAP, MAX_ENTRIES> {
+    pub const fn new() -> DynamicTable<CAP, MAX_ENTRIES> {
+        DynamicTable{
+            buffer: [0u8; CAP],
+            head: 0,
+            tail: 0,
+            h2_used_size: 0,
+            entries: [None; MAX_ENTRIES],
+            entries_start: 0,
+            entries_count: 0,
+            next_seq_id: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries_count
+    }
+
+    pub fn get(&self, index: usize) -> Option<Item> {
+        if index >= self.entries_count {
+            return None;
+        }
+        let idx = (self.entries_start + self.entries_count - 1 - index) % MAX_ENTRIES;
+        let entry = self.entries[idx].unwrap();
+        let name = &self.buffer[entry.name_start .. entry.name_start + entry.name_len];
+        let value_start = entry.name_start + entry.name_len;
+        let value = &self.buffer[value_start .. value_start + entry.value_len];
+        Some(Item{
+            name,
+            value: Some(value),
+            index,
+        })
+    }
+
+    pub fn prepend(&mut self, name: &[u8], value: &[u8]) -> Option<Item> {
+        let phys_len = name.len() + value.len();
+        match self.make_room(phys_len + 32) {
+            MakeRoomResult::NoRoom => return None,
+            MakeRoomResult::Enough => (),
+        }
+
+        let start = loop {
+            if self.entries_count == 0 {
+                self.head = 0;
+                self.tail = 0;
+            }
+            if self.head >= self.tail {
+                let upper_piece = CAP - self.head;
+                if phys_len <= upper_piece {
+                    break self.head;
+                }
+                if phys_len <= self.tail {
+                    self.set_waste_after(upper_piece);
+                    break 0;
+                }
+            } else {
+                let avail = self.tail - self.head;
+                if phys_len <= avail {
+                    break self.head;
+                }
+            }
+            if !self.evict_oldest() {
+                return None;
+            }
+        };
+
+        self.buffer[start .. start + name.len()].copy_from_slice(name);
+        self.buffer[start + name.len() .. start + phys_len].copy_from_slice(value);
+
+        self.head = start + phys_len;
+        if self.head == CAP {
+            self.head = 0;
+        }
+        self.h2_used_size += phys_len + 32;
+
+        let new_idx = (self.entries_start + self.entries_count) % MAX_ENTRIES;
+        self.entries[new_idx] = Some(Entry{
+            name_start: start,
+            name_len: name.len(),
+            value_len: value.len(),
+            waste_after: 0,
+        });
+        self.entries_count += 1;
+
+        Some(Item{
+            name: &self.buffer[start .. start + name.len()],
+            value: Some(&self.buffer[start + name.len() .. start + phys_len]),
+            index: 0,
+        })
+    }
+
+    fn set_waste_after(&mut self, wasted: usize) -> () {
+        if self.entries_count == 0 {
+            return;
+        }
+        let newest_idx = (self.entries_start + self.entries_count - 1) % MAX_ENTRIES;
+        if let Some(ref mut entry) = self.entries[newest_idx] {
+            entry.waste_after = wasted;
+        }
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        if self.entries_count == 0 {
+            return false;
+        }
+        let entry = self.entries[self.entries_start].take().unwrap();
+        self.entries_start = (self.entries_start + 1) % MAX_ENTRIES;
+        self.entries_count -= 1;
+        self.h2_used_size -= entry.h2_size();
+        self.tail = (self.tail + entry.phys_len() + entry.waste_after) % CAP;
+        true
+    }
+
+    fn make_room(&mut self, space: usize) -> MakeRoomResult {
+        while self.h2_used_size + space > CAP && self.entries_count > 0 {
+            self.evict_oldest();
+        }
+        if self.h2_used_size + space > CAP {
+            MakeRoomResult::NoRoom
+        } else {
+            MakeRoomResult::Enough
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn basic_prepend_and_get() {
+        let mut dyntbl: DynamicTable<100, 4> = DynamicTable::new();
+        dyntbl.prepend(b"hello", b"world");
+        assert_eq!(dyntbl.len(), 1);
+        assert_eq!(dyntbl.get(0).unwrap().name, b"hello");
+        assert_eq!(dyntbl.get(0).unwrap().value.unwrap(), b"world");
+        assert!(dyntbl.get(1).is_none());
+    }
+
+    #[test]
+    fn evicts_oldest_when_full() {
+        const KEY0: &[u8] = b"hello0";
+        const VALUE0: &[u8] = b"world0";
+        const KEY1: &[u8] = b"hello1";
+        const VALUE1: &[u8] = b"world1";
+        const KEY2: &[u8] = b"hello2";
+        const VALUE2: &[u8] = b"world2";
+        // large enough to hold 2 key-values, but not 3.
+        let mut dyntbl: DynamicTable<100, 4> = DynamicTable::new();
+        dyntbl.prepend(KEY0, VALUE0);
+        dyntbl.prepend(KEY1, VALUE1);
+        dyntbl.prepend(KEY2, VALUE2);
+        assert_eq!(dyntbl.len(), 2);
+        assert_eq!(dyntbl.get(0).unwrap().name, KEY2);
+        assert_eq!(dyntbl.get(1).unwrap().name, KEY1);
+    }
+
+    #[test]
+    fn rejects_entry_larger_than_capacity() {
+        let mut dyntbl: DynamicTable<10, 1> = DynamicTable::new();
+        assert!(dyntbl.prepend(b"hello", b"world").is_none());
+        assert_eq!(dyntbl.len(), 0);
+    }
+
+    #[test]
+    fn realistic_hpack_size_needs_far_fewer_entry_slots_than_capacity_bytes() {
+        // RFC 7541's default SETTINGS_HEADER_TABLE_SIZE. Every entry costs at
+        // least 32 bytes of h2_used_size, so 129 (4096 / 32 + 1) entry slots
+        // is already a safe bound -- nowhere near one slot per capacity byte.
+        let mut dyntbl: DynamicTable<4096, 129> = DynamicTable::new();
+        for i in 0..200u32 {
+            let name = format!("header-{}", i);
+            let value = format!("value-{}", i);
+            assert!(dyntbl.prepend(name.as_bytes(), value.as_bytes()).is_some());
+        }
+        assert_eq!(dyntbl.get(0).unwrap().name, b"header-199");
+    }
+
+    #[test]
+    fn reuses_space_from_evicted_entries_across_many_wraps() {
+        // small enough that entries are evicted and the ring wraps several
+        // times over the lifetime of the table.
+        let mut dyntbl: DynamicTable<64, 3> = DynamicTable::new();
+        for i in 0..50u32 {
+            let name = format!("k{}", i);
+            let value = format!("v{}", i);
+            dyntbl.prepend(name.as_bytes(), value.as_bytes());
+            assert_eq!(dyntbl.get(0).unwrap().name, name.as_bytes());
+            assert_eq!(dyntbl.get(0).unwrap().value.unwrap(), value.as_bytes());
+        }
+    }
+}
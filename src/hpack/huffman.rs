@@ -4,9 +4,17 @@ use super::super::*;
 pub fn decode(
     input: &[u8],
 ) -> Result<Vec<u8>, &'static str> {
+    let mut res = vec!();
+    decode_into(&mut res, input)?;
+    Ok(res)
+}
+
+/// Like `decode`, but appends into a caller-supplied buffer instead of
+/// allocating a fresh one, so a decode session can reuse the same buffer
+/// (e.g. via `Arena`) across many strings.
+pub fn decode_into(out: &mut Vec<u8>, input: &[u8]) -> Result<(), &'static str> {
     let iter = BitIterator::new(input);
     let mut walker = HuffmanTreeWalker::new(&*HUFFMAN_TREE);
-    let mut res = vec!();
     for x in iter {
         let c = walker.advance(x);
         match c {
@@ -14,7 +22,7 @@ pub fn decode(
             Some(c) => {
                 match c {
                     Char::Normal(c) => {
-                        res.push(c);
+                        out.push(c);
                     },
                     _ => {
                         return Err("decode error on Huffman compressed headers.");
@@ -41,7 +49,7 @@ pub fn decode(
             }
         }
     }
-    Ok(res)
+    Ok(())
 }
 
 pub fn encode(out: &mut Vec<u8>, input: &[u8]) -> () {
@@ -22,13 +22,13 @@ pub fn parse_uint(
         if input.is_empty() {
             return Err("shortage of input on deserialization.");
         }
+        if buf_len >= buf.len() {
+            return Err("corrupted data.");
+        }
         let (byte, inp) = input.split_first().unwrap();
         input = inp;
         buf[buf_len] = byte & 0x7Fu8;
         buf_len += 1;
-        if buf_len > buf.len() {
-            return Err("corrupted data.");
-        }
         if byte & 0x80u8 == 0 {
             break;
         }
@@ -37,11 +37,18 @@ pub fn parse_uint(
     let mut res = 0u64;
     while buf_len > 0 {
         buf_len -= 1;
+        // `res <<= 7` below would silently drop `res`'s top 7 bits once
+        // they're non-zero, turning a value that doesn't fit in `u64` into
+        // some smaller, unrelated one instead of failing — exactly the
+        // decompression-bomb integer this bound exists to reject.
+        if res & !(u64::max_value() >> 7) != 0 {
+            return Err("integer overflow in HPACK varint");
+        }
         res <<= 7;
         res |= buf[buf_len] as u64;
     }
-    res += mask as u64;
-    
+    res = res.checked_add(mask as u64).ok_or("integer overflow in HPACK varint")?;
+
     Ok((input, res))
 }
 
@@ -159,6 +166,42 @@ mod test {
         assert!(b.is_empty());
     }
 
+    #[test]
+    fn test_parse_err_overlong_continuation() {
+        // 11 bytes, each with the continuation bit set: longer than any
+        // value representable in 64 bits, so this must be rejected rather
+        // than overrun the internal accumulator buffer.
+        let mut buf: Vec<u8> = vec!(31u8);
+        buf.extend(std::iter::repeat(0x80u8).take(11));
+        let err = parse_uint(buf.as_slice(), 5);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_parse_err_value_overflow() {
+        // 9 continuation bytes (0xFF) and a terminating 0x7F, each carrying a
+        // 0x7F payload: 10 bytes, within the 10-byte cap, but the encoded
+        // value doesn't fit in a u64, so this must fail outright rather than
+        // silently wrap.
+        let mut buf: Vec<u8> = vec!(31u8);
+        buf.extend(std::iter::repeat(0xFFu8).take(9));
+        buf.push(0x7Fu8);
+        let err = parse_uint(buf.as_slice(), 5);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_parse_err_value_overflow_is_distinguishable_from_overlong() {
+        // Same 10-byte shape as test_parse_err_value_overflow, but this
+        // asserts on the error message itself: a value that overflows u64
+        // must be reported distinctly from an overlong (11+ byte) run, not
+        // just happen to both return some Err.
+        let mut buf: Vec<u8> = vec!(31u8);
+        buf.extend(std::iter::repeat(0xFFu8).take(9));
+        buf.push(0x7Fu8);
+        assert_eq!(parse_uint(buf.as_slice(), 5), Err("integer overflow in HPACK varint"));
+    }
+
     #[test]
     fn test_serialize_parse_exhaustive() {
         for prefix_bits in 1usize..9usize {
@@ -1,4 +1,6 @@
+mod arena;
 mod dynamic_table;
+mod fixed_dynamic_table;
 mod int;
 mod huffman;
 mod huffman_codes;
@@ -7,21 +9,97 @@ mod static_table;
 mod string;
 
 use std::fmt::{Debug, Formatter};
+pub use arena::Arena;
 use dynamic_table::*;
+pub use fixed_dynamic_table::DynamicTable as FixedDynamicTable;
 use int::*;
 use self_owned_slice::*;
 use string::*;
+pub use string::HuffmanPolicy;
 use static_table::*;
 use super::*;
 
+const INDEXED: (u8, u8) = (0x80, 0x80);
+const LITERAL_WITH_INDEXING: (u8, u8) = (0xC0, 0x40);
+const LITERAL_WITHOUT_INDEXING: (u8, u8) = (0xF0, 0);
+const LITERAL_NEVER_INDEXING: (u8, u8) = (0xF0, 0x10);
+const SIZE_UPDATE: (u8, u8) = (0xE0, 0x20);
+
+/// Header names whose values are, by default, handed out as a
+/// `SelfOwnedSlice::Guarded`, which is zeroized as soon as the field is
+/// dropped. The value is first decoded into an ordinary buffer (or read
+/// from the dynamic table) and then copied into the guarded one; those
+/// earlier copies are not zeroized. Callers can widen or narrow this via
+/// `Decoder::set_sensitive_headers`.
+pub fn default_sensitive_headers() -> std::collections::BTreeSet<Vec<u8>> {
+    [
+        &b"authorization"[..],
+        b"cookie",
+        b"set-cookie",
+        b"proxy-authorization",
+    ].iter().map(|s| s.to_vec()).collect()
+}
+
 pub struct Decoder {
     dyntbl: DynamicTable,
+    max_dyntbl_size: usize,
+    pending: Vec<u8>,
+    arena: Option<Arena>,
+    sensitive_headers: std::collections::BTreeSet<Vec<u8>>,
 }
 
 impl Decoder {
     pub fn with_capacity(cap: usize) -> Decoder {
         Decoder{
             dyntbl: DynamicTable::with_capacity(cap),
+            max_dyntbl_size: cap,
+            pending: vec!(),
+            arena: None,
+            sensitive_headers: default_sensitive_headers(),
+        }
+    }
+
+    /// Like `with_capacity`, but routes the scratch copies used while
+    /// decoding literal strings (e.g. expanding a Huffman-coded value)
+    /// through `arena` instead of allocating a fresh buffer per field.
+    pub fn with_capacity_in(cap: usize, arena: Arena) -> Decoder {
+        Decoder{
+            dyntbl: DynamicTable::with_capacity(cap),
+            max_dyntbl_size: cap,
+            pending: vec!(),
+            arena: Some(arena),
+            sensitive_headers: default_sensitive_headers(),
+        }
+    }
+
+    /// Replaces the set of header names (matched case-sensitively, as sent
+    /// on the wire) whose values get the zero-on-drop `Guarded` treatment.
+    /// Defaults to `default_sensitive_headers()`.
+    pub fn set_sensitive_headers(&mut self, names: std::collections::BTreeSet<Vec<u8>>) -> () {
+        self.sensitive_headers = names;
+    }
+
+    fn guard_if_sensitive(&self, name: &SelfOwnedSlice, value: SelfOwnedSlice) -> SelfOwnedSlice {
+        if self.sensitive_headers.contains(name.as_slice()) {
+            SelfOwnedSlice::new_guarded(value.as_slice().to_vec())
+        } else {
+            value
+        }
+    }
+
+    fn parse_owned_string<'a, 'b>(
+        &'a mut self,
+        input: &'b [u8],
+    ) -> Result<(&'b [u8], SelfOwnedSlice), &'static str> {
+        match &mut self.arena {
+            Some(arena) => {
+                let (rem, bytes) = parse_string_into(input, arena.buf())?;
+                Ok((rem, SelfOwnedSlice::new_with_slice(bytes)))
+            },
+            None => {
+                let (rem, value) = parse_string(input)?;
+                Ok((rem, SelfOwnedSlice::new_with_maybe_owned_slice(value)))
+            },
         }
     }
 
@@ -33,10 +111,6 @@ impl Decoder {
             return Err("shortage of input on deserialization.");
         }
 
-        const INDEXED: (u8, u8) = (0x80, 0x80);
-        const LITERAL_WITH_INDEXING: (u8, u8) = (0xC0, 0x40);
-        const LITERAL_WITHOUT_INDEXING: (u8, u8) = (0xF0, 0);
-        const LITERAL_NEVER_INDEXING: (u8, u8) = (0xF0, 0x10);
         match input[0] {
             x if check_prefix(x, INDEXED) => {
                 let (rem, idx) = parse_uint(input, 7)?;
@@ -47,6 +121,7 @@ impl Decoder {
                         Err("request a indexed no-value header field.")
                     },
                     Some(value) => {
+                        let value = self.guard_if_sensitive(&name, value);
                         Ok((rem, HeaderField::Index((name, value))))
                     },
                 }
@@ -57,31 +132,29 @@ impl Decoder {
                     let (name, _) = self.get_from_index_table(idx as usize)?;
                     (rem, name)
                 } else {
-                    let (rem, name) = parse_string(rem)?;
                     // could uselessly copy `name`.
                     // but it is of little possiblity.
-                    (rem, SelfOwnedSlice::new_with_maybe_owned_slice(name))
+                    self.parse_owned_string(rem)?
                 };
-                let (rem, value) = parse_string(rem)?;
+                let (rem, value) = self.parse_owned_string(rem)?;
                 let item = self.dyntbl.prepend(name.as_slice(), value.as_slice());
                 match item {
                     Some(item) => {
-                        Ok((rem, HeaderField::Index((
-                            SelfOwnedSlice::new_with_cached_str(&item.name),
-                            SelfOwnedSlice::new_with_cached_str(&item.value.unwrap()),
-                        ))))
+                        let name = SelfOwnedSlice::new_with_cached_str(&item.name);
+                        let value = SelfOwnedSlice::new_with_cached_str(&item.value.unwrap());
+                        let value = self.guard_if_sensitive(&name, value);
+                        Ok((rem, HeaderField::Index((name, value))))
                     },
                     None => {
-                        Ok((rem, HeaderField::Index((
-                            name,
-                            SelfOwnedSlice::new_with_maybe_owned_slice(value),
-                        ))))
+                        let value = self.guard_if_sensitive(&name, value);
+                        Ok((rem, HeaderField::Index((name, value))))
                     }
                 }
             },
             x if check_prefix(x, LITERAL_WITHOUT_INDEXING) => {
                 let (rem, name, value) = self.parse_without_indexing(input)?;
-                Ok((rem, 
+                let value = self.guard_if_sensitive(&name, value);
+                Ok((rem,
                     HeaderField::NotIndex((name, value)),
                 ))
             },
@@ -89,41 +162,265 @@ impl Decoder {
                 let (rem, name, value) = self.parse_without_indexing(input)?;
                 let (raw, _) = input.split_at(input.len() - rem.len());
                 let raw = SelfOwnedSlice::new_with_slice(raw);
-                Ok((rem, 
+                let value = self.guard_if_sensitive(&name, value);
+                Ok((rem,
                     HeaderField::NeverIndex((name, value, raw)),
                 ))
             },
+            x if check_prefix(x, SIZE_UPDATE) => {
+                let (rem, new_size) = parse_uint(input, 5)?;
+                let new_size = new_size as usize;
+                if new_size > self.max_dyntbl_size {
+                    warn!(
+                        "dynamic table size update {} exceeds the negotiated maximum {}.",
+                        new_size, self.max_dyntbl_size);
+                    return Err("dynamic table size update exceeds negotiated maximum.");
+                }
+                self.dyntbl.set_max_size(new_size);
+                Ok((rem, HeaderField::SizeUpdate(new_size)))
+            },
             _ => unreachable!(),
         }
     }
 
     fn parse_without_indexing<'a, 'b>(
-        &'a self,
+        &'a mut self,
         input: &'b [u8],
     ) -> Result<(&'b [u8], SelfOwnedSlice, SelfOwnedSlice), &'static str> {
+        let (rem, idx) = parse_uint(input, 4)?;
+        if idx > 0 {
+            let (name, _) = self.get_from_index_table(idx as usize)?;
+            let (rem, value) = self.parse_owned_string(rem)?;
+            Ok((rem, name, value))
+        } else {
+            let (rem, name) = self.parse_owned_string(rem)?;
+            let (rem, value) = self.parse_owned_string(rem)?;
+            Ok((rem, name, value))
+        }
+    }
+
+    /// Runs a complete, concatenated HPACK-encoded header block through
+    /// `parse_header_field` until it's exhausted — the block-level
+    /// counterpart to `parse_header_field` for a caller (e.g. a fully
+    /// reassembled HEADERS + CONTINUATION sequence) that already has the
+    /// whole block in hand and doesn't need per-field control.
+    pub fn decode_block(&mut self, block: &[u8]) -> Result<Vec<HeaderField>, &'static str> {
+        let mut fields = vec!();
+        let mut input = block;
+        while !input.is_empty() {
+            let (rem, field) = self.parse_header_field(input)?;
+            fields.push(field);
+            input = rem;
+        }
+        Ok(fields)
+    }
+}
+
+impl Decoder {
+    /// Like `parse_header_field`, but a literal field whose name/value was
+    /// transmitted raw (not Huffman-coded) borrows those octets directly from
+    /// `input` instead of copying them into an owned buffer. Huffman-coded
+    /// strings and names/values resolved from the static/dynamic tables are
+    /// still materialized, since in those cases the bytes don't live in
+    /// `input` to begin with. A Dynamic Table Size Update is applied to the
+    /// dynamic table but not returned: decoding goes straight on to the
+    /// field after it, so an update with nothing after it in `input` is
+    /// reported as a shortage of input.
+    pub fn parse_header_field_borrowed<'a, 'b>(
+        &'a mut self,
+        input: &'b [u8],
+    ) -> Result<(&'b [u8], BorrowedHeaderField<'b>), &'static str> {
+        if input.is_empty() {
+            return Err("shortage of input on deserialization.");
+        }
+
+        match input[0] {
+            x if check_prefix(x, INDEXED) => {
+                let (rem, idx) = parse_uint(input, 7)?;
+                let (name, value) = self.get_from_index_table(idx as usize)?;
+                match value {
+                    None => {
+                        warn!("request a indexed no-value header field. index: {}", idx);
+                        Err("request a indexed no-value header field.")
+                    },
+                    Some(value) => {
+                        Ok((rem, BorrowedHeaderField::Index((name, value))))
+                    },
+                }
+            },
+            x if check_prefix(x, LITERAL_WITH_INDEXING) => {
+                // this variant always mutates the dynamic table, so the
+                // resulting name/value live in the table's own storage
+                // regardless of how they were transmitted; there is nothing
+                // left to borrow from `input`.
+                let (rem, idx) = parse_uint(input, 6)?;
+                let (rem, name) = if idx > 0 {
+                    let (name, _) = self.get_from_index_table(idx as usize)?;
+                    (rem, name)
+                } else {
+                    let (rem, name) = parse_string(rem)?;
+                    (rem, SelfOwnedSlice::new_with_maybe_owned_slice(name))
+                };
+                let (rem, value) = parse_string(rem)?;
+                let item = self.dyntbl.prepend(name.as_slice(), value.as_slice());
+                match item {
+                    Some(item) => {
+                        Ok((rem, BorrowedHeaderField::Index((
+                            SelfOwnedSlice::new_with_cached_str(&item.name),
+                            SelfOwnedSlice::new_with_cached_str(&item.value.unwrap()),
+                        ))))
+                    },
+                    None => {
+                        Ok((rem, BorrowedHeaderField::Index((
+                            name,
+                            SelfOwnedSlice::new_with_maybe_owned_slice(value),
+                        ))))
+                    }
+                }
+            },
+            x if check_prefix(x, LITERAL_WITHOUT_INDEXING) => {
+                let (rem, name, value) = self.parse_without_indexing_borrowed(input)?;
+                Ok((rem, BorrowedHeaderField::NotIndex((name, value))))
+            },
+            x if check_prefix(x, LITERAL_NEVER_INDEXING) => {
+                let (rem, name, value) = self.parse_without_indexing_borrowed(input)?;
+                let (raw, _) = input.split_at(input.len() - rem.len());
+                Ok((rem, BorrowedHeaderField::NeverIndex((name, value, raw))))
+            },
+            x if check_prefix(x, SIZE_UPDATE) => {
+                let (rem, new_size) = parse_uint(input, 5)?;
+                let new_size = new_size as usize;
+                if new_size > self.max_dyntbl_size {
+                    warn!(
+                        "dynamic table size update {} exceeds the negotiated maximum {}.",
+                        new_size, self.max_dyntbl_size);
+                    return Err("dynamic table size update exceeds negotiated maximum.");
+                }
+                self.dyntbl.set_max_size(new_size);
+                self.parse_header_field_borrowed(rem)
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_without_indexing_borrowed<'a, 'b>(
+        &'a self,
+        input: &'b [u8],
+    ) -> Result<(&'b [u8], BorrowedSlice<'b>, BorrowedSlice<'b>), &'static str> {
         let (rem, idx) = parse_uint(input, 4)?;
         if idx > 0 {
             let (name, _) = self.get_from_index_table(idx as usize)?;
             let (rem, value) = parse_string(rem)?;
-            Ok((rem, 
-                name,
-                SelfOwnedSlice::new_with_maybe_owned_slice(value),
-            ))
+            Ok((rem, BorrowedSlice::Owned(name), borrow_maybe_owned(value)))
         } else {
             let (rem, name) = parse_string(rem)?;
             let (rem, value) = parse_string(rem)?;
-            Ok((rem,
-                SelfOwnedSlice::new_with_maybe_owned_slice(name),
-                SelfOwnedSlice::new_with_maybe_owned_slice(value),
-            ))
+            Ok((rem, borrow_maybe_owned(name), borrow_maybe_owned(value)))
+        }
+    }
+
+    /// Like `decode_block`, but borrows literal name/value bytes directly
+    /// from `block` instead of copying them — the block-level counterpart
+    /// to `parse_header_field_borrowed`.
+    pub fn decode_block_borrowed<'a, 'b>(
+        &'a mut self,
+        block: &'b [u8],
+    ) -> Result<Vec<BorrowedHeaderField<'b>>, &'static str> {
+        let mut fields = vec!();
+        let mut input = block;
+        while !input.is_empty() {
+            let (rem, field) = self.parse_header_field_borrowed(input)?;
+            fields.push(field);
+            input = rem;
+        }
+        Ok(fields)
+    }
+}
+
+impl Decoder {
+    /// Appends `input` to this decoder's internal buffer and decodes as many
+    /// complete header fields as it can. A header field split across a
+    /// buffer/frame boundary (e.g. by a CONTINUATION frame) is left
+    /// buffered internally rather than erroring out, so the caller can just
+    /// pass the next chunk to the following `feed` call to resume. Because
+    /// `parse_header_field` only mutates `self.dyntbl` after a field has
+    /// been fully parsed, a partial field at the edge of `input` can never
+    /// leave the dynamic table in a half-applied state.
+    pub fn feed(&mut self, input: &[u8]) -> Result<DecodeProgress, &'static str> {
+        self.pending.extend_from_slice(input);
+        let buf = std::mem::take(&mut self.pending);
+        let mut fields = vec!();
+        let mut offset = 0usize;
+        loop {
+            let rest = &buf[offset..];
+            if rest.is_empty() {
+                break;
+            }
+            match self.parse_header_field(rest) {
+                Ok((rem, field)) => {
+                    fields.push(field);
+                    offset = buf.len() - rem.len();
+                },
+                Err("shortage of input on deserialization.") => {
+                    // a partial field at the tail end; wait for more bytes.
+                    break;
+                },
+                Err(e) => {
+                    self.pending = buf[offset..].to_vec();
+                    return Err(e);
+                },
+            }
         }
+        self.pending = buf[offset..].to_vec();
+        Ok(DecodeProgress{fields, consumed: input.len()})
     }
 }
 
+/// The result of a single `Decoder::feed` call: every header field that
+/// could be fully decoded from the bytes fed so far, and how many of the
+/// bytes just passed in were consumed. `feed` takes every byte it is given
+/// into the decoder's internal buffer, so this is always `input.len()`; a
+/// partial trailing field stays in that buffer until the next call.
+pub struct DecodeProgress {
+    pub fields: Vec<HeaderField>,
+    pub consumed: usize,
+}
+
+/// A name/value that was decoded without an allocation because it was
+/// transmitted raw and lives in the caller's `input` buffer, or one that had
+/// to be materialized anyway (Huffman expansion).
+pub enum BorrowedSlice<'b> {
+    Borrowed(&'b [u8]),
+    Owned(SelfOwnedSlice),
+}
+
+impl<'b> Sliceable for BorrowedSlice<'b> {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            BorrowedSlice::Borrowed(x) => x,
+            BorrowedSlice::Owned(x) => x.as_slice(),
+        }
+    }
+}
+
+fn borrow_maybe_owned<'b>(v: MaybeOwnedSlice<'b>) -> BorrowedSlice<'b> {
+    match v {
+        MaybeOwnedSlice::Slice(x) => BorrowedSlice::Borrowed(x),
+        MaybeOwnedSlice::Vec(x) => BorrowedSlice::Owned(SelfOwnedSlice::new_with_vec(x)),
+    }
+}
+
+pub enum BorrowedHeaderField<'b> {
+    Index((SelfOwnedSlice, SelfOwnedSlice)),
+    NotIndex((BorrowedSlice<'b>, BorrowedSlice<'b>)),
+    NeverIndex((BorrowedSlice<'b>, BorrowedSlice<'b>, &'b [u8])),
+}
+
 pub enum HeaderField {
     Index((SelfOwnedSlice, SelfOwnedSlice)),
     NotIndex((SelfOwnedSlice, SelfOwnedSlice)),
     NeverIndex((SelfOwnedSlice, SelfOwnedSlice, SelfOwnedSlice)),
+    SizeUpdate(usize),
 }
 
 impl Debug for HeaderField {
@@ -147,6 +444,10 @@ impl Debug for HeaderField {
                 fmt_bytes(&mut res, name.as_slice());
                 res.push('=');
                 fmt_bytes(&mut res, value.as_slice());
+            },
+            HeaderField::SizeUpdate(new_size) => {
+                res.push_str("HeaderField::SizeUpdate(");
+                res.push_str(&new_size.to_string());
             }
         }
         res.push(')');
@@ -237,6 +538,8 @@ fn check_prefix(x: u8, criteria: (u8, u8)) -> bool {
 pub struct Encoder {
     dyntbl: DynamicTable,
     static_seeker: static_table::Seeker,
+    huffman_policy: HuffmanPolicy,
+    pending_size_update: Option<usize>,
 }
 
 impl Encoder {
@@ -244,9 +547,38 @@ impl Encoder {
         Encoder{
             dyntbl: DynamicTable::with_capacity(cap),
             static_seeker: static_table::Seeker::new(),
+            huffman_policy: HuffmanPolicy::WhenSmaller,
+            pending_size_update: None,
         }
     }
 
+    /// Controls whether `encode_header_field` Huffman-codes literal
+    /// name/value strings. Defaults to `HuffmanPolicy::WhenSmaller`, which
+    /// picks whichever representation is actually shorter per field.
+    pub fn set_huffman_policy(&mut self, policy: HuffmanPolicy) -> () {
+        self.huffman_policy = policy;
+    }
+
+    /// Emits a Dynamic Table Size Update instruction and resizes this
+    /// encoder's own dynamic table to match, so the peer's decoder can be
+    /// told about a renegotiated capacity before the next header field.
+    pub fn encode_size_update(&mut self, out: &mut Vec<u8>, new_size: usize) -> () {
+        serialize_uint(out, new_size as u64, 5, 0x20);
+        self.dyntbl.set_max_size(new_size);
+    }
+
+    /// Like `encode_size_update`, but evicts the table now and only queues
+    /// the wire instruction, to be emitted by the next `encode_header_field`
+    /// call rather than into a buffer the caller may not have in hand yet
+    /// (e.g. when a peer's SETTINGS_HEADER_TABLE_SIZE arrives between header
+    /// blocks). If another resize is queued before any field is encoded,
+    /// only the latest size is sent, matching RFC 7541 §6.3's "the new
+    /// maximum size" semantics.
+    pub fn resize(&mut self, new_size: usize) -> () {
+        self.dyntbl.set_max_size(new_size);
+        self.pending_size_update = Some(new_size);
+    }
+
     pub fn encode_header_field(
         &mut self,
         out: &mut Vec<u8>,
@@ -254,6 +586,9 @@ impl Encoder {
         name: &[u8],
         value: &[u8],
     ) -> () {
+        if let Some(new_size) = self.pending_size_update.take() {
+            serialize_uint(out, new_size as u64, 5, 0x20);
+        }
         match hint {
             CacheHint::PREFER_CACHE => {
                 let with_caching = |out: &mut Vec<u8>, idx: usize| {
@@ -275,8 +610,22 @@ impl Encoder {
             },
         };
     }
+
+    /// Encodes every field in `fields`, in order, appending each to `out` —
+    /// the block-level counterpart to `encode_header_field` for a caller
+    /// (e.g. a full header block for a HEADERS frame) that doesn't need to
+    /// interleave anything of its own between fields.
+    pub fn encode_block<'a, I>(&mut self, out: &mut Vec<u8>, fields: I) -> ()
+    where
+        I: IntoIterator<Item = (CacheHint, &'a [u8], &'a [u8])>,
+    {
+        for (hint, name, value) in fields {
+            self.encode_header_field(out, hint, name, value);
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheHint {
     PREFER_CACHE,
     PREFER_NOT_CACHE,
@@ -293,8 +642,13 @@ impl Encoder {
         idx_encoder: T,
     ) -> ()
     where T: 'static + Fn(&mut Vec<u8>, usize) -> () {
+        let dyn_match = self.dyntbl.find(name, value);
+
         let idx = self.static_seeker.seek_with_name_value(name, value)
-            .or_else(|| {self.dyntbl.seek_with_name_value(name, value)});
+            .or_else(|| match dyn_match {
+                Some((idx, MatchKind::NameAndValue)) => Some(idx),
+                _ => None,
+            });
         match idx {
             Some(idx) => {
                 serialize_uint(out, idx as u64, 7, 0x80);
@@ -304,19 +658,19 @@ impl Encoder {
         }
 
         let idx = self.static_seeker.seek_with_name(name)
-            .or_else(|| {self.dyntbl.seek_with_name(name)});
+            .or_else(|| dyn_match.map(|(idx, _)| idx));
         match idx {
             Some(idx) => {
                 idx_encoder(out, idx);
-                serialize_string(out, value);
+                serialize_string_with_policy(out, value, self.huffman_policy);
                 return;
             },
             None => (),
         }
 
         idx_encoder(out, 0);
-        serialize_string(out, name);
-        serialize_string(out, value);
+        serialize_string_with_policy(out, name, self.huffman_policy);
+        serialize_string_with_policy(out, value, self.huffman_policy);
     }
 }
 
@@ -502,6 +856,218 @@ mod test {
         assert_eq!(decoder.dyntbl.len(), 0);
     }
 
+    #[test]
+    fn parse_header_field_size_update() {
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 42, 5, 0x20);
+        let mut decoder = Decoder::with_capacity(100);
+
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        match res {
+            HeaderField::SizeUpdate(new_size) => assert_eq!(new_size, 42),
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn parse_header_field_size_update_too_large() {
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 101, 5, 0x20);
+        let mut decoder = Decoder::with_capacity(100);
+
+        assert!(decoder.parse_header_field(buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn encoder_size_update_shrinks_own_table() {
+        const KEY0: &[u8] = b"hello0";
+        const VALUE0: &[u8] = b"world0";
+        let mut encoder = Encoder::with_capacity(100);
+        let mut buf: Vec<u8> = vec!();
+        encoder.encode_size_update(&mut buf, 0);
+        encoder.encode_header_field(&mut buf, CacheHint::PREFER_CACHE, KEY0, VALUE0);
+
+        let mut decoder = Decoder::with_capacity(100);
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        match res {
+            HeaderField::SizeUpdate(new_size) => assert_eq!(new_size, 0),
+            _ => panic!(),
+        };
+        decoder.parse_header_field(rem).unwrap();
+        assert_eq!(decoder.dyntbl.len(), 0);
+    }
+
+    #[test]
+    fn resize_queues_size_update_for_the_next_header_field() {
+        const KEY0: &[u8] = b"hello0";
+        const VALUE0: &[u8] = b"world0";
+        let mut encoder = Encoder::with_capacity(100);
+        encoder.resize(0);
+
+        let mut buf: Vec<u8> = vec!();
+        encoder.encode_header_field(&mut buf, CacheHint::PREFER_CACHE, KEY0, VALUE0);
+
+        let mut decoder = Decoder::with_capacity(100);
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        match res {
+            HeaderField::SizeUpdate(new_size) => assert_eq!(new_size, 0),
+            _ => panic!(),
+        };
+        decoder.parse_header_field(rem).unwrap();
+        assert_eq!(decoder.dyntbl.len(), 0);
+
+        // only the first field after a resize carries the instruction.
+        let mut buf2: Vec<u8> = vec!();
+        encoder.encode_header_field(&mut buf2, CacheHint::PREFER_CACHE, KEY0, VALUE0);
+        assert!(!buf2.is_empty());
+        let (_, res2) = decoder.parse_header_field(buf2.as_slice()).unwrap();
+        match res2 {
+            HeaderField::SizeUpdate(_) => panic!("size update should not repeat"),
+            _ => (),
+        };
+    }
+
+    #[test]
+    fn parse_header_field_guards_sensitive_header_value() {
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 0, 6, 0x40);
+        serialize_string(&mut buf, b"authorization");
+        serialize_string(&mut buf, b"Bearer s3cr3t-token");
+        let mut decoder = Decoder::with_capacity(100);
+
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        match res {
+            HeaderField::Index((name, value)) => {
+                assert_eq!(name.as_slice(), b"authorization");
+                assert_eq!(value.as_slice(), b"Bearer s3cr3t-token");
+                match value {
+                    SelfOwnedSlice::Guarded(_) => (),
+                    _ => panic!("expected a guarded value"),
+                }
+            },
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn parse_header_field_does_not_guard_ordinary_header_value() {
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 0, 6, 0x40);
+        serialize_string(&mut buf, b"age");
+        serialize_string(&mut buf, b"123");
+        let mut decoder = Decoder::with_capacity(100);
+
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        match res {
+            HeaderField::Index((_, value)) => {
+                match value {
+                    SelfOwnedSlice::Guarded(_) => panic!("did not expect a guarded value"),
+                    _ => (),
+                }
+            },
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn with_capacity_in_decodes_through_arena() {
+        const AGE: &[u8] = b"www.example.com";
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 0, 6, 0x40);
+        serialize_string(&mut buf, b"age");
+        serialize_string(&mut buf, AGE);
+        let mut decoder = Decoder::with_capacity_in(0, Arena::new(64));
+
+        let (rem, res) = decoder.parse_header_field(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        match res {
+            HeaderField::Index((name, value)) => {
+                assert_eq!(name.as_slice(), b"age");
+                assert_eq!(value.as_slice(), AGE);
+            },
+            _ => panic!(),
+        };
+    }
+
+    #[test]
+    fn feed_whole_field_in_one_call() {
+        const AGE: &[u8] = b"123";
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 21, 6, 0x40);
+        serialize_string(&mut buf, AGE);
+        let mut decoder = Decoder::with_capacity(100);
+
+        let progress = decoder.feed(buf.as_slice()).unwrap();
+        assert_eq!(progress.consumed, buf.len());
+        assert_eq!(progress.fields.len(), 1);
+        match &progress.fields[0] {
+            HeaderField::Index((name, value)) => {
+                assert_eq!(name.as_slice(), b"age");
+                assert_eq!(value.as_slice(), AGE);
+            },
+            _ => panic!(),
+        };
+        assert_eq!(decoder.dyntbl.len(), 1);
+    }
+
+    #[test]
+    fn feed_resumes_across_split_field() {
+        const AGE: &[u8] = b"123";
+        let mut buf: Vec<u8> = vec!();
+        serialize_uint(&mut buf, 21, 6, 0x40);
+        serialize_string(&mut buf, AGE);
+        let split = buf.len() - 1;
+        let mut decoder = Decoder::with_capacity(100);
+
+        // the field is truncated one byte short: no field decoded yet, and
+        // the dynamic table must not have been mutated.
+        let progress = decoder.feed(&buf[..split]).unwrap();
+        assert!(progress.fields.is_empty());
+        assert_eq!(decoder.dyntbl.len(), 0);
+
+        // the rest of the field arrives in the next chunk.
+        let progress = decoder.feed(&buf[split..]).unwrap();
+        assert_eq!(progress.fields.len(), 1);
+        match &progress.fields[0] {
+            HeaderField::Index((name, value)) => {
+                assert_eq!(name.as_slice(), b"age");
+                assert_eq!(value.as_slice(), AGE);
+            },
+            _ => panic!(),
+        };
+        assert_eq!(decoder.dyntbl.len(), 1);
+    }
+
+    #[test]
+    fn feed_decodes_multiple_fields_across_calls() {
+        const AGE: &[u8] = b"123";
+        let mut field0: Vec<u8> = vec!();
+        serialize_uint(&mut field0, 21, 6, 0x40);
+        serialize_string(&mut field0, AGE);
+        let mut field1: Vec<u8> = vec!();
+        serialize_uint(&mut field1, 21, 4, 0);
+        serialize_string(&mut field1, AGE);
+
+        let mut decoder = Decoder::with_capacity(100);
+        let mut first_chunk = field0.clone();
+        first_chunk.extend_from_slice(&field1[..1]);
+        let progress = decoder.feed(first_chunk.as_slice()).unwrap();
+        assert_eq!(progress.fields.len(), 1);
+
+        let progress = decoder.feed(&field1[1..]).unwrap();
+        assert_eq!(progress.fields.len(), 1);
+        match &progress.fields[0] {
+            HeaderField::NotIndex((name, value)) => {
+                assert_eq!(name.as_slice(), b"age");
+                assert_eq!(value.as_slice(), AGE);
+            },
+            _ => panic!(),
+        };
+    }
+
     #[test]
     fn random() {
         let names: Vec<&'static [u8]> = vec![
@@ -552,4 +1118,65 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn encode_block_and_decode_block_round_trip_a_whole_header_block() {
+        let fields: Vec<(CacheHint, &[u8], &[u8])> = vec![
+            (CacheHint::PREFER_CACHE, b":method", b"GET"),
+            (CacheHint::PREFER_NOT_CACHE, b"x-custom-header", b"some-value"),
+            (CacheHint::NEVER_CACHE, b"authorization", b"secret"),
+        ];
+
+        let mut encoder = Encoder::with_capacity(100);
+        let mut buf = vec!();
+        encoder.encode_block(&mut buf, fields.clone());
+
+        let mut decoder = Decoder::with_capacity(100);
+        let decoded = decoder.decode_block(buf.as_slice()).unwrap();
+        assert_eq!(decoded.len(), fields.len());
+        for (field, (_, o_name, o_value)) in decoded.iter().zip(fields.iter()) {
+            match field {
+                HeaderField::Index((name, value))
+                | HeaderField::NotIndex((name, value))
+                | HeaderField::NeverIndex((name, value, _)) => {
+                    assert_eq!(name.as_slice(), *o_name);
+                    assert_eq!(value.as_slice(), *o_value);
+                },
+                HeaderField::SizeUpdate(_) => panic!("unexpected {:?}", field),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_block_borrowed_matches_decode_block() {
+        let mut encoder = Encoder::with_capacity(100);
+        let mut buf = vec!();
+        let fields: Vec<(CacheHint, &[u8], &[u8])> = vec![
+            (CacheHint::PREFER_CACHE, b":method", b"GET"),
+            (CacheHint::PREFER_NOT_CACHE, b"x-custom-header", b"some-value"),
+        ];
+        encoder.encode_block(&mut buf, fields);
+
+        let mut decoder = Decoder::with_capacity(100);
+        let owned = decoder.decode_block(buf.as_slice()).unwrap();
+
+        let mut decoder = Decoder::with_capacity(100);
+        let borrowed = decoder.decode_block_borrowed(buf.as_slice()).unwrap();
+
+        assert_eq!(owned.len(), borrowed.len());
+        for (o, b) in owned.iter().zip(borrowed.iter()) {
+            let (o_name, o_value) = match o {
+                HeaderField::Index((name, value)) => (name.as_slice(), value.as_slice()),
+                HeaderField::NotIndex((name, value)) => (name.as_slice(), value.as_slice()),
+                other => panic!("unexpected {:?}", other),
+            };
+            let (b_name, b_value) = match b {
+                BorrowedHeaderField::Index((name, value)) => (name.as_slice(), value.as_slice()),
+                BorrowedHeaderField::NotIndex((name, value)) => (name.as_slice(), value.as_slice()),
+                BorrowedHeaderField::NeverIndex((name, value, _)) => (name.as_slice(), value.as_slice()),
+            };
+            assert_eq!(o_name, b_name);
+            assert_eq!(o_value, b_value);
+        }
+    }
 }
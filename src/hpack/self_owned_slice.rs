@@ -3,10 +3,39 @@ use std::fmt::{Debug, Formatter, Error};
 use super::{CachedStr, MaybeOwnedSlice};
 use super::super::Sliceable;
 
+/// Backing storage for `SelfOwnedSlice::Guarded`: every byte is overwritten
+/// with zero on drop via a volatile write, so the optimizer cannot elide the
+/// store as dead code. This guards against sensitive values (credentials,
+/// session cookies) lingering in reclaimed heap memory; unlike the full
+/// guarded-vec pattern it does not additionally `mlock` the page against
+/// being swapped to disk.
+pub struct GuardedBuf(Vec<u8>);
+
+impl GuardedBuf {
+    fn new(v: Vec<u8>) -> GuardedBuf {
+        GuardedBuf(v)
+    }
+}
+
+impl GuardedBuf {
+    fn zeroize(&mut self) {
+        for b in self.0.iter_mut() {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+    }
+}
+
+impl Drop for GuardedBuf {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 pub enum SelfOwnedSlice {
     Array((u8, [u8; 15])),
     Vec(Vec<u8>),
     CachedStr(CachedStr),
+    Guarded(GuardedBuf),
 }
 
 impl SelfOwnedSlice {
@@ -41,6 +70,14 @@ impl SelfOwnedSlice {
         }
     }
 
+    /// Unlike the other constructors, this never uses the inline `Array`
+    /// small-value optimization: a short sensitive value (e.g. a 4-digit
+    /// cookie prefix) still deserves the zero-on-drop guarantee, which the
+    /// stack-allocated `Array` variant cannot provide.
+    pub fn new_guarded(v: Vec<u8>) -> SelfOwnedSlice {
+        SelfOwnedSlice::Guarded(GuardedBuf::new(v))
+    }
+
     fn try_new_with_array(v: &[u8]) -> Option<SelfOwnedSlice> {
         if v.len() < 16 {
             let mut dst = [0u8; 15];
@@ -53,7 +90,7 @@ impl SelfOwnedSlice {
     }
 }
 
-impl Sliceable<u8> for SelfOwnedSlice {
+impl Sliceable for SelfOwnedSlice {
     fn as_slice(&self) -> &[u8] {
         match self {
             SelfOwnedSlice::Array((len, ref arr)) => {
@@ -62,6 +99,7 @@ impl Sliceable<u8> for SelfOwnedSlice {
             },
             SelfOwnedSlice::Vec(ref x) => x.as_slice(),
             SelfOwnedSlice::CachedStr(ref x) => x.as_slice(),
+            SelfOwnedSlice::Guarded(ref x) => x.0.as_slice(),
         }
     }
 }
@@ -156,4 +194,17 @@ mod test {
         let o = format!("{:?}", b"0123456789ABCDEF");
         assert_eq!(t, o);
     }
+
+    #[test]
+    fn guarded_exposes_its_bytes_like_any_other_variant() {
+        let s = SelfOwnedSlice::new_guarded(b"s3cr3t".to_vec());
+        assert_eq!(s.as_slice(), b"s3cr3t");
+    }
+
+    #[test]
+    fn guarded_zeroizes() {
+        let mut guard = GuardedBuf::new(b"s3cr3t".to_vec());
+        guard.zeroize();
+        assert_eq!(guard.0.as_slice(), [0u8; 6]);
+    }
 }
@@ -82,6 +82,124 @@ pub const RAW_TABLE: [Item; 62] = [
     Item {name: b"www-authenticate", value: None},
 ];
 
+/// The QPACK static table (RFC 9204 Appendix A), used by HTTP/3. Index 0 is
+/// a blank placeholder, matching `RAW_TABLE`'s convention of keeping this
+/// table's indices 1-based.
+pub const QPACK_STATIC_TABLE: [Item; 100] = [
+    Item{name: b"", value: None},
+
+    Item{name: b":authority", value: Some(b"")},
+    Item{name: b":path", value: Some(b"/")},
+    Item{name: b"age", value: Some(b"0")},
+    Item{name: b"content-disposition", value: Some(b"")},
+    Item{name: b"content-length", value: Some(b"0")},
+    Item{name: b"cookie", value: Some(b"")},
+    Item{name: b"date", value: Some(b"")},
+    Item{name: b"etag", value: Some(b"")},
+    Item{name: b"if-modified-since", value: Some(b"")},
+    Item{name: b"if-none-match", value: Some(b"")},
+    Item{name: b"last-modified", value: Some(b"")},
+    Item{name: b"link", value: Some(b"")},
+    Item{name: b"location", value: Some(b"")},
+    Item{name: b"referer", value: Some(b"")},
+    Item{name: b"set-cookie", value: Some(b"")},
+
+    Item{name: b":method", value: Some(b"CONNECT")},
+    Item{name: b":method", value: Some(b"DELETE")},
+    Item{name: b":method", value: Some(b"GET")},
+    Item{name: b":method", value: Some(b"HEAD")},
+    Item{name: b":method", value: Some(b"OPTIONS")},
+    Item{name: b":method", value: Some(b"POST")},
+    Item{name: b":method", value: Some(b"PUT")},
+    Item{name: b":scheme", value: Some(b"http")},
+    Item{name: b":scheme", value: Some(b"https")},
+
+    Item{name: b":status", value: Some(b"103")},
+    Item{name: b":status", value: Some(b"200")},
+    Item{name: b":status", value: Some(b"304")},
+    Item{name: b":status", value: Some(b"404")},
+    Item{name: b":status", value: Some(b"503")},
+
+    Item{name: b"accept", value: Some(b"*/*")},
+    Item{name: b"accept", value: Some(b"application/dns-message")},
+    Item{name: b"accept-encoding", value: Some(b"gzip, deflate, br")},
+    Item{name: b"accept-ranges", value: Some(b"bytes")},
+    Item{name: b"access-control-allow-headers", value: Some(b"cache-control")},
+    Item{name: b"access-control-allow-headers", value: Some(b"content-type")},
+    Item{name: b"access-control-allow-origin", value: Some(b"*")},
+
+    Item{name: b"cache-control", value: Some(b"max-age=0")},
+    Item{name: b"cache-control", value: Some(b"max-age=2592000")},
+    Item{name: b"cache-control", value: Some(b"max-age=604800")},
+    Item{name: b"cache-control", value: Some(b"no-cache")},
+    Item{name: b"cache-control", value: Some(b"no-store")},
+    Item{name: b"cache-control", value: Some(b"public, max-age=31536000")},
+    Item{name: b"content-encoding", value: Some(b"br")},
+    Item{name: b"content-encoding", value: Some(b"gzip")},
+
+    Item{name: b"content-type", value: Some(b"application/dns-message")},
+    Item{name: b"content-type", value: Some(b"application/javascript")},
+    Item{name: b"content-type", value: Some(b"application/json")},
+    Item{name: b"content-type", value: Some(b"application/x-www-form-urlencoded")},
+    Item{name: b"content-type", value: Some(b"image/gif")},
+    Item{name: b"content-type", value: Some(b"image/jpeg")},
+    Item{name: b"content-type", value: Some(b"image/png")},
+    Item{name: b"content-type", value: Some(b"text/css")},
+    Item{name: b"content-type", value: Some(b"text/html; charset=utf-8")},
+    Item{name: b"content-type", value: Some(b"text/plain")},
+    Item{name: b"content-type", value: Some(b"text/plain;charset=utf-8")},
+
+    Item{name: b"range", value: Some(b"bytes=0-")},
+    Item{name: b"strict-transport-security", value: Some(b"max-age=31536000")},
+    Item{name: b"strict-transport-security",
+        value: Some(b"max-age=31536000; includesubdomains")},
+    Item{name: b"strict-transport-security",
+        value: Some(b"max-age=31536000; includesubdomains; preload")},
+    Item{name: b"vary", value: Some(b"accept-encoding")},
+    Item{name: b"vary", value: Some(b"origin")},
+    Item{name: b"x-content-type-options", value: Some(b"nosniff")},
+    Item{name: b"x-xss-protection", value: Some(b"1; mode=block")},
+
+    Item{name: b":status", value: Some(b"100")},
+    Item{name: b":status", value: Some(b"204")},
+    Item{name: b":status", value: Some(b"206")},
+    Item{name: b":status", value: Some(b"302")},
+    Item{name: b":status", value: Some(b"400")},
+    Item{name: b":status", value: Some(b"403")},
+    Item{name: b":status", value: Some(b"421")},
+    Item{name: b":status", value: Some(b"425")},
+    Item{name: b":status", value: Some(b"500")},
+
+    Item{name: b"accept-language", value: Some(b"")},
+    Item{name: b"access-control-allow-credentials", value: Some(b"FALSE")},
+    Item{name: b"access-control-allow-credentials", value: Some(b"TRUE")},
+    Item{name: b"access-control-allow-headers", value: Some(b"*")},
+    Item{name: b"access-control-allow-methods", value: Some(b"get")},
+    Item{name: b"access-control-allow-methods", value: Some(b"get, post, options")},
+    Item{name: b"access-control-allow-methods", value: Some(b"options")},
+    Item{name: b"access-control-expose-headers", value: Some(b"content-length")},
+    Item{name: b"access-control-request-headers", value: Some(b"content-type")},
+    Item{name: b"access-control-request-method", value: Some(b"get")},
+    Item{name: b"access-control-request-method", value: Some(b"post")},
+    Item{name: b"alt-svc", value: Some(b"clear")},
+    Item{name: b"authorization", value: Some(b"")},
+    Item{name: b"content-security-policy",
+        value: Some(b"script-src 'none'; object-src 'none'; base-uri 'none'")},
+    Item{name: b"early-data", value: Some(b"1")},
+    Item{name: b"expect-ct", value: Some(b"")},
+    Item{name: b"forwarded", value: Some(b"")},
+    Item{name: b"if-range", value: Some(b"")},
+    Item{name: b"origin", value: Some(b"")},
+    Item{name: b"purpose", value: Some(b"prefetch")},
+    Item{name: b"server", value: Some(b"")},
+    Item{name: b"timing-allow-origin", value: Some(b"*")},
+    Item{name: b"upgrade-insecure-requests", value: Some(b"1")},
+    Item{name: b"user-agent", value: Some(b"")},
+    Item{name: b"x-forwarded-for", value: Some(b"")},
+    Item{name: b"x-frame-options", value: Some(b"deny")},
+    Item{name: b"x-frame-options", value: Some(b"sameorigin")},
+];
+
 type HeaderIndexMap = BTreeMap<&'static [u8], usize>;
 type SizedHeaderIndexMap = BTreeMap<usize, HeaderIndexMap>;
 
@@ -95,13 +213,18 @@ pub struct Seeker {
 }
 
 impl Seeker {
-    pub fn new() -> Seeker {
+    /// Builds a seeker over any static table shaped like `RAW_TABLE`: a
+    /// `'static` array of `Item`s with a blank placeholder at index 0.
+    /// This is what lets the same seeking logic back both an HPACK
+    /// encoder (over `RAW_TABLE`) and a QPACK one (over
+    /// `QPACK_STATIC_TABLE`).
+    pub fn from_table(table: &'static [Item]) -> Seeker {
         let mut res = Seeker{
             full_headers: SizedHeaderValueIndexMap::new(),
             no_value_headers: SizedHeaderIndexMap::new()};
-        for idx in 1..RAW_TABLE.len() {
-            let name = RAW_TABLE[idx].name;
-            let value = RAW_TABLE[idx].value;
+        for idx in 1..table.len() {
+            let name = table[idx].name;
+            let value = table[idx].value;
             match value {
                 Some(value) => {
                     let r = res.full_headers
@@ -128,6 +251,14 @@ impl Seeker {
         res
     }
 
+    pub fn new() -> Seeker {
+        Seeker::from_table(&RAW_TABLE)
+    }
+
+    pub fn new_qpack() -> Seeker {
+        Seeker::from_table(&QPACK_STATIC_TABLE)
+    }
+
     pub fn seek_with_name(&self, name: &[u8]) -> Option<usize> {
         let header_idx_map = self.no_value_headers.get(&name.len())?;
         let idx = header_idx_map.get(name)?;
@@ -181,4 +312,41 @@ mod test {
         let res = seeker.seek_with_name_value(b":status", b"NOT_EXIST");
         assert!(res.is_none());
     }
+
+    #[test]
+    fn qpack_seeker_exhaustive() {
+        let seeker = Seeker::new_qpack();
+
+        for oracle_idx in 1..QPACK_STATIC_TABLE.len() {
+            let header = QPACK_STATIC_TABLE[oracle_idx].name;
+            let value = QPACK_STATIC_TABLE[oracle_idx].value;
+
+            let trial_idx = match value {
+                Some(ref v) => seeker.seek_with_name_value(header, v),
+                None => seeker.seek_with_name(header),
+            };
+
+            assert_eq!(trial_idx, Some(oracle_idx));
+        }
+    }
+
+    #[test]
+    fn qpack_seeker_nonexist_header() {
+        let seeker = Seeker::new_qpack();
+        let res = seeker.seek_with_name(b"NOT_EXIST");
+        assert!(res.is_none());
+    }
+
+    #[test]
+    fn from_table_matches_dedicated_constructors() {
+        let hpack_seeker = Seeker::from_table(&RAW_TABLE);
+        assert_eq!(hpack_seeker.seek_with_name_value(b":method", b"GET"), Some(2));
+        assert_eq!(hpack_seeker.seek_with_name_value(b":method", b"GET"),
+                   Seeker::new().seek_with_name_value(b":method", b"GET"));
+
+        let qpack_seeker = Seeker::from_table(&QPACK_STATIC_TABLE);
+        assert_eq!(qpack_seeker.seek_with_name_value(b":method", b"GET"), Some(18));
+        assert_eq!(qpack_seeker.seek_with_name_value(b":method", b"GET"),
+                   Seeker::new_qpack().seek_with_name_value(b":method", b"GET"));
+    }
 }
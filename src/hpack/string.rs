@@ -1,18 +1,50 @@
 use random::Source;
 use super::huffman;
+use super::huffman_codes::RAW_TABLE;
 use super::int::*;
 use super::super::Sliceable;
 
+#[derive(Debug, Clone, Copy)]
+pub enum HuffmanPolicy {
+    /// always emit the Huffman form, even if it is not the shorter one.
+    Always,
+    /// never emit the Huffman form.
+    Never,
+    /// emit whichever form is strictly shorter (the RFC 7541 §5.2 default).
+    WhenSmaller,
+}
+
+/// Sums the per-symbol Huffman code lengths of `input` and rounds up to
+/// whole octets, i.e. the exact length `input` would occupy once
+/// Huffman-encoded (including the all-ones EOS padding of the final byte).
+pub fn huffman_encoded_len(input: &[u8]) -> usize {
+    let bits: usize = input.iter()
+        .map(|&b| RAW_TABLE[b as usize].bits)
+        .sum();
+    (bits + 7) / 8
+}
+
 pub fn serialize_string(out: &mut Vec<u8>, input: &[u8]) -> () {
-    if input.len() < 16 {
-        serialize_raw_string(out, input)
-    } else {
+    serialize_string_with_policy(out, input, HuffmanPolicy::WhenSmaller)
+}
+
+pub fn serialize_string_with_policy(
+    out: &mut Vec<u8>,
+    input: &[u8],
+    policy: HuffmanPolicy,
+) -> () {
+    let use_huffman = match policy {
+        HuffmanPolicy::Always => true,
+        HuffmanPolicy::Never => false,
+        HuffmanPolicy::WhenSmaller => huffman_encoded_len(input) < input.len(),
+    };
+    if use_huffman {
         let mut tmp: Vec<u8> = vec!();
-        {
-            huffman::encode(&mut tmp, input);
-        }
+        huffman::encode(&mut tmp, input);
         serialize_uint(out, tmp.len() as u64, 7, 0x80);
         out.append(&mut tmp);
+    } else {
+        serialize_raw_string(out, input);
     }
 }
 
@@ -44,6 +76,35 @@ pub fn parse_string(input: &[u8]) -> Result<(&[u8], MaybeOwnedSlice), &'static s
     }
 }
 
+/// Like `parse_string`, but the decoded bytes land in `scratch` (cleared
+/// first) instead of a freshly allocated `Vec`, so a caller holding onto a
+/// reusable buffer (e.g. an `Arena`) doesn't pay for one allocation per
+/// string literal.
+pub fn parse_string_into<'b, 's>(
+    input: &'b [u8],
+    scratch: &'s mut Vec<u8>,
+) -> Result<(&'b [u8], &'s [u8]), &'static str> {
+    if input.is_empty() {
+        return Err("shortage of input on deserialization.");
+    }
+
+    let huffman_encoded = (input[0] & 0x80) == 0;
+    let (buf, len) = parse_uint(input, 7)?;
+    let len = len as usize;
+    if buf.len() < len {
+        return Err("shortage of input on deserialization.");
+    }
+    let (buf, rem) = buf.split_at(len);
+
+    scratch.clear();
+    if huffman_encoded {
+        scratch.extend_from_slice(buf);
+    } else {
+        huffman::decode_into(scratch, buf)?;
+    }
+    Ok((rem, scratch.as_slice()))
+}
+
 pub enum MaybeOwnedSlice<'a> {
     Slice(&'a [u8]),
     Vec(Vec<u8>),
@@ -123,6 +184,24 @@ mod test {
         assert_eq!(res.as_slice(), b"www.example.com");
     }
 
+    #[test]
+    fn parse_string_into_reuses_scratch() {
+        let buf = vec![
+            0x8C, 0xF1, 0xE3, 0xC2, 0xE5,
+            0xF2, 0x3A, 0x6B, 0xA0, 0xAB,
+            0x90, 0xF4, 0xFF];
+        let mut scratch: Vec<u8> = vec!();
+        let (rem, res) = parse_string_into(buf.as_slice(), &mut scratch).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(res, b"www.example.com");
+
+        // a second, unrelated call must not see leftover bytes.
+        let buf2 = vec![0x0A, 0x63, 0x75, 0x73, 0x74, 0x6F, 0x6D, 0x2D, 0x6B, 0x65, 0x79];
+        let (rem, res) = parse_string_into(buf2.as_slice(), &mut scratch).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(res, b"custom-key");
+    }
+
     fn randomized_vec<T: Eq + Clone>(alphabet: &[T], terminator: T) -> Vec<T> {
         let mut rng = random::default();
         let len = alphabet.len();
@@ -137,6 +216,44 @@ mod test {
         out
     }
 
+    #[test]
+    fn serialize_string_incompressible_stays_raw() {
+        // every byte value once: the control bytes and the bytes >= 0x80
+        // have long codes under the static Huffman table, so taken together
+        // the Huffman form is longer than the raw one and the raw form wins.
+        let input: Vec<u8> = (0u8..=255).collect();
+        let mut buf: Vec<u8> = vec!();
+        serialize_string(&mut buf, input.as_slice());
+        assert_eq!(buf[0] & 0x80, 0, "expected raw (H bit clear): {:?}", buf);
+        let (rem, res) = parse_string(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(res.as_slice(), input.as_slice());
+    }
+
+    #[test]
+    fn serialize_string_compressible_uses_huffman() {
+        // ASCII letters are cheap in the static Huffman table, so a long
+        // run of them should come out smaller Huffman-encoded.
+        let input = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let mut buf: Vec<u8> = vec!();
+        serialize_string(&mut buf, input);
+        assert_eq!(buf[0] & 0x80, 0x80, "expected Huffman (H bit set): {:?}", buf);
+        let (rem, res) = parse_string(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(res.as_slice(), input);
+    }
+
+    #[test]
+    fn serialize_string_with_policy_never_stays_raw() {
+        let input = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let mut buf: Vec<u8> = vec!();
+        serialize_string_with_policy(&mut buf, input, HuffmanPolicy::Never);
+        assert_eq!(buf[0] & 0x80, 0);
+        let (rem, res) = parse_string(buf.as_slice()).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(res.as_slice(), input);
+    }
+
     #[test]
     fn random() {
         let mut rng = random::default();
@@ -3,6 +3,8 @@ extern crate futures;
 #[macro_use] extern crate log;
 extern crate base62;
 extern crate once_cell;
+extern crate tokio_rustls;
+extern crate webpki;
 
 mod parsers;
 mod serializers;
@@ -16,14 +18,21 @@ pub use error::{Error, ALL_ERRORS};
 mod frames;
 pub use frames::*;
 
+mod message;
+pub use message::{Request, Response};
+
+mod flow_control;
+
+mod priority;
+
 mod net;
-pub use net::{handshake, Config};
+pub use net::{handshake, handshake_tcp, connect, connect_tcp, accept_tls, connect_tls, disconnect, Config, Role, Connection};
 
-mod connection;
-pub use connection::Connection;
+mod enhanced_slice;
 
 mod hpack;
-pub use hpack::{EncoderField, DecoderField};
+pub use hpack::{HeaderField, CacheHint};
 
 mod sliceable;
 use sliceable::Sliceable;
+pub use sliceable::AnySliceable;
@@ -16,30 +16,30 @@ fn listen_on(addr: &SocketAddr) -> impl Future<Item=(), Error=()> {
             let cfg = Config{
                 sender_queue_size: 100,
                 my_h2_settings: vec!((SettingKey::MaxConcurrentStreams, 123)),
+                use_huffman: true,
+                keepalive_interval: None,
+                keepalive_timeout: std::time::Duration::from_secs(10),
             };
-            let _ = handshake(cfg, conn, |conn, frame| {
+            let _ = handshake_tcp(cfg, conn, |conn, frame| {
                 info!("got a frame: {:?}", frame);
                 match frame {
                     Frame::Headers(ref f) if f.end_stream && f.end_headers => {
                         info!("responding");
                         let builder = SendHeadersFrameBuilder::new()
                             .set_stream_id(1)
-                            .append_header_field(EncoderField::ToCache((
+                            .append_header_field(CacheHint::PREFER_CACHE,
                                 AnySliceable::new(b":status".to_vec()),
-                                AnySliceable::new(b"200".to_vec()),
-                            )))
+                                AnySliceable::new(b"200".to_vec()))
                             .set_end_headers()
                             .set_end_stream();
                         // let builder = SendHeadersFrameBuilder::new()
                         //     .set_stream_id(2)
-                        //     .append_header_field(EncoderField::ToCache((
+                        //     .append_header_field(CacheHint::PREFER_CACHE,
                         //         AnySliceable::new(b":methd".to_vec()),
-                        //         AnySliceable::new(b"GET".to_vec()),
-                        //     )))
-                        //     .append_header_field(EncoderField::ToCache((
+                        //         AnySliceable::new(b"GET".to_vec()))
+                        //     .append_header_field(CacheHint::PREFER_CACHE,
                         //         AnySliceable::new(b":path".to_vec()),
-                        //         AnySliceable::new(b"/".to_vec()),
-                        //     )))
+                        //         AnySliceable::new(b"/".to_vec()))
                         //     .set_end_headers()
                         //     .set_end_stream();
                         conn.send_frame(SendFrame::Headers(SendHeadersFrame::new(builder)));
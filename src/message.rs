@@ -0,0 +1,167 @@
+use super::*;
+
+/// RFC 7540 §8.1.2.2's connection-specific headers, carried over from
+/// HTTP/1.1 but meaningless (and forbidden) over HTTP/2: the framing layer
+/// already manages what these used to negotiate.
+const CONNECTION_SPECIFIC_HEADERS: [&[u8]; 5] =
+    [b"connection", b"keep-alive", b"proxy-connection", b"transfer-encoding", b"upgrade"];
+
+/// RFC 7540 §8.1.2.1: a typed view of a request's decoded header block, with
+/// the pseudo-headers split out from the ordinary ones. Built with
+/// `ReceivedHeadersFrame::as_request`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Request {
+    pub method: Vec<u8>,
+    pub scheme: Vec<u8>,
+    pub path: Vec<u8>,
+    pub authority: Option<Vec<u8>>,
+    /// RFC 8441's extended-CONNECT pseudo-header. Only legal when the peer
+    /// has negotiated `SETTINGS_ENABLE_CONNECT_PROTOCOL`, which callers pass
+    /// in via `as_request`'s `connect_protocol_enabled` argument.
+    pub protocol: Option<Vec<u8>>,
+    pub headers: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// RFC 7540 §8.1.2.4: a typed view of a response's decoded header block.
+/// Built with `ReceivedHeadersFrame::as_response`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Response {
+    pub status: Vec<u8>,
+    pub headers: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Request {
+    const ALLOWED_PSEUDO: [&'static [u8]; 5] =
+        [b":method", b":scheme", b":path", b":authority", b":protocol"];
+
+    pub(crate) fn from_header_block(
+        fields: &[HeaderField],
+        stream_id: u32,
+        connect_protocol_enabled: bool,
+    ) -> Result<Request, Error> {
+        let (pseudo, headers) = split_pseudo_and_regular(fields, stream_id, &Self::ALLOWED_PSEUDO)?;
+
+        let mut method = None;
+        let mut scheme = None;
+        let mut path = None;
+        let mut authority = None;
+        let mut protocol = None;
+        for (name, value) in pseudo {
+            match name.as_slice() {
+                b":method" => method = Some(value),
+                b":scheme" => scheme = Some(value),
+                b":path" => path = Some(value),
+                b":authority" => authority = Some(value),
+                b":protocol" => protocol = Some(value),
+                _ => unreachable!("ALLOWED_PSEUDO is exhaustive"),
+            }
+        }
+
+        if protocol.is_some() && !connect_protocol_enabled {
+            return Err(missing_protocol_gate(stream_id));
+        }
+
+        Ok(Request{
+            method: method.ok_or_else(|| missing_pseudo(stream_id, ":method"))?,
+            scheme: scheme.ok_or_else(|| missing_pseudo(stream_id, ":scheme"))?,
+            path: path.ok_or_else(|| missing_pseudo(stream_id, ":path"))?,
+            authority,
+            protocol,
+            headers,
+        })
+    }
+}
+
+impl Response {
+    const ALLOWED_PSEUDO: [&'static [u8]; 1] = [b":status"];
+
+    pub(crate) fn from_header_block(
+        fields: &[HeaderField],
+        stream_id: u32,
+    ) -> Result<Response, Error> {
+        let (pseudo, headers) = split_pseudo_and_regular(fields, stream_id, &Self::ALLOWED_PSEUDO)?;
+
+        let mut status = None;
+        for (name, value) in pseudo {
+            match name.as_slice() {
+                b":status" => status = Some(value),
+                _ => unreachable!("ALLOWED_PSEUDO is exhaustive"),
+            }
+        }
+
+        Ok(Response{
+            status: status.ok_or_else(|| missing_pseudo(stream_id, ":status"))?,
+            headers,
+        })
+    }
+}
+
+/// Splits `fields` into its leading pseudo-headers and the ordinary headers
+/// that follow, enforcing RFC 7540 §8.1.2.1's malformed-message rules along
+/// the way: a pseudo-header after a regular header, one not in
+/// `allowed_pseudo`, or a duplicated one is a stream-level PROTOCOL_ERROR, as
+/// is any connection-specific header (RFC 7540 §8.1.2.2) or a `te` value
+/// other than `trailers`. `HeaderField::SizeUpdate` entries are HPACK
+/// bookkeeping, not header fields, and are skipped.
+fn split_pseudo_and_regular(
+    fields: &[HeaderField],
+    stream_id: u32,
+    allowed_pseudo: &[&[u8]],
+) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, Vec<(Vec<u8>, Vec<u8>)>), Error> {
+    let mut pseudo: Vec<(Vec<u8>, Vec<u8>)> = vec!();
+    let mut regular: Vec<(Vec<u8>, Vec<u8>)> = vec!();
+
+    for field in fields {
+        let (name, value) = match field {
+            HeaderField::Index((name, value)) => (name.as_slice(), value.as_slice()),
+            HeaderField::NotIndex((name, value)) => (name.as_slice(), value.as_slice()),
+            HeaderField::NeverIndex((name, value, _)) => (name.as_slice(), value.as_slice()),
+            HeaderField::SizeUpdate(_) => continue,
+        };
+
+        if name.starts_with(b":") {
+            if !regular.is_empty() {
+                return Err(malformed(
+                    stream_id,
+                    format!("pseudo-header {} appears after a regular header", String::from_utf8_lossy(name))));
+            }
+            if !allowed_pseudo.contains(&name) {
+                return Err(malformed(
+                    stream_id,
+                    format!("unknown pseudo-header {}", String::from_utf8_lossy(name))));
+            }
+            if pseudo.iter().any(|(n, _)| n.as_slice() == name) {
+                return Err(malformed(
+                    stream_id,
+                    format!("duplicated pseudo-header {}", String::from_utf8_lossy(name))));
+            }
+            pseudo.push((name.to_vec(), value.to_vec()));
+        } else {
+            if CONNECTION_SPECIFIC_HEADERS.contains(&name) {
+                return Err(malformed(
+                    stream_id,
+                    format!("connection-specific header {} is forbidden over HTTP/2", String::from_utf8_lossy(name))));
+            }
+            if name == b"te" && value != b"trailers" {
+                return Err(malformed(stream_id, "the te header must be \"trailers\" or absent".to_string()));
+            }
+            regular.push((name.to_vec(), value.to_vec()));
+        }
+    }
+
+    Ok((pseudo, regular))
+}
+
+fn malformed(stream_id: u32, message: String) -> Error {
+    Error::new_for_stream(error::Code::ProtocolError, stream_id, message)
+}
+
+fn missing_pseudo(stream_id: u32, name: &str) -> Error {
+    malformed(stream_id, format!("missing required pseudo-header {}", name))
+}
+
+fn missing_protocol_gate(stream_id: u32) -> Error {
+    malformed(
+        stream_id,
+        ":protocol requires SETTINGS_ENABLE_CONNECT_PROTOCOL to have been negotiated".to_string())
+}
@@ -1,45 +1,278 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{IoSlice, Write};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{Ordering, AtomicBool, AtomicU32};
 use std::time::{Duration, Instant};
 use tokio::prelude::*;
+use futures::future;
 use tokio::io;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio_rustls::{TlsAcceptor, TlsConnector, rustls};
+use webpki::DNSNameRef;
 use random::Source;
 use super::*;
+use super::flow_control::{FlowController, WindowUpdateError};
+use super::priority::PriorityTree;
 
-pub fn handshake<F>(
+/// Accepts an incoming HTTP/2 connection over any transport, not just a raw
+/// `TcpStream` — in particular a `tokio_rustls::TlsStream` can be passed in
+/// directly, which is what lets this crate run over TLS. See `handshake_tcp`
+/// for the plain-TCP convenience wrapper and `accept_tls` for the helper
+/// that performs the TLS accept and ALPN check before calling this.
+pub fn handshake<S, F>(
     cfg: Config,
-    tcp: TcpStream,
+    io: S,
     on_frame: F,
 ) -> Result<Arc<Connection>, super::error::Error>
-where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
-    tcp.set_nodelay(true).unwrap();
+where S: 'static + Send + AsyncRead + AsyncWrite,
+      F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
     let (tx, rx) = channel::<Frame>(cfg.sender_queue_size);
-    let mut conn = Connection::new(on_frame, tx);
+    let mut conn = Connection::new(Role::Server, on_frame, tx);
     info!("start to handshake an incoming connection {}", base62::encode(conn.id));
+    if !cfg.use_huffman {
+        conn.header_encoder.lock().unwrap().set_huffman_policy(hpack::HuffmanPolicy::Never);
+    }
     Arc::get_mut(&mut conn).unwrap()
         .update_sender_h2_settings(cfg.my_h2_settings);
-    let (input, output) = tcp.split();
+    let (input, output) = io.split();
     start_receive_coroutine(input, conn.clone());
     start_send_coroutine(rx, output, conn.clone());
+    if let Some(interval) = cfg.keepalive_interval {
+        start_keepalive_coroutine(conn.clone(), interval, cfg.keepalive_timeout);
+    }
+    Ok(conn)
+}
+
+/// Thin `TcpStream`-specific convenience wrapper around `handshake`: sets
+/// `TCP_NODELAY`, a socket option a generic `AsyncRead + AsyncWrite` (e.g. a
+/// TLS stream) has no equivalent of, before delegating.
+pub fn handshake_tcp<F>(
+    cfg: Config,
+    tcp: TcpStream,
+    on_frame: F,
+) -> Result<Arc<Connection>, super::error::Error>
+where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
+    tcp.set_nodelay(true).unwrap();
+    handshake(cfg, tcp, on_frame)
+}
+
+/// Performs the server side of a TLS handshake over `tcp` with `acceptor`,
+/// then asserts the peer negotiated the `"h2"` ALPN protocol (RFC 7540
+/// §3.3) before entering the HTTP/2 preface exchange via `handshake`.
+/// Rejects with a connection-level error if ALPN negotiated anything else,
+/// including nothing at all.
+pub fn accept_tls<F>(
+    cfg: Config,
+    acceptor: TlsAcceptor,
+    tcp: TcpStream,
+    on_frame: F,
+) -> impl Future<Item = Arc<Connection>, Error = super::error::Error>
+where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
+    tcp.set_nodelay(true).unwrap();
+    acceptor.accept(tcp)
+        .map_err(|err| Error::new(
+            error::Level::ConnectionLevel,
+            error::Code::ConnectError,
+            format!("TLS accept failed: {:?}", err)))
+        .and_then(move |tls| {
+            require_h2_alpn(&tls).and_then(|()| handshake(cfg, tls, on_frame))
+        })
+}
+
+/// The client-side counterpart to `handshake`: originates a connection
+/// instead of accepting one. RFC 7540 §3.5 has the client (not the server)
+/// send the connection preface, so this writes `PREFACE` followed by our
+/// initial SETTINGS frame directly, before the send coroutine (which only
+/// knows how to serialize `Frame`s, and the preface isn't one) ever starts;
+/// on the receive side there is no preface to read, so it joins the same
+/// `read_settings`-then-`receive_coroutine_continuation` chain `handshake`
+/// uses via `start_receive_coroutine_from_settings`.
+pub fn connect<S, F>(
+    cfg: Config,
+    io: S,
+    on_frame: F,
+) -> Result<Arc<Connection>, super::error::Error>
+where S: 'static + Send + AsyncRead + AsyncWrite,
+      F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
+    let (tx, rx) = channel::<Frame>(cfg.sender_queue_size);
+    let mut conn = Connection::new(Role::Client, on_frame, tx);
+    info!("start to connect to a peer as a client on connection {}", base62::encode(conn.id));
+    if !cfg.use_huffman {
+        conn.header_encoder.lock().unwrap().set_huffman_policy(hpack::HuffmanPolicy::Never);
+    }
+    {
+        let whole: &mut Settings = &mut Arc::get_mut(&mut conn).unwrap().my_h2_settings.lock().unwrap();
+        for (key, val) in &cfg.my_h2_settings {
+            whole.set(key.clone(), *val);
+        }
+    }
+    apply_own_initial_window_size(&conn.flow_control, &cfg.my_h2_settings);
+
+    let mut preface_and_settings = PREFACE.as_bytes().to_vec();
+    preface_and_settings.extend(Frame::Settings(SettingsFrame::new(false, cfg.my_h2_settings)).serialize());
+
+    let (input, output) = io.split();
+    start_receive_coroutine_from_settings(input, conn.clone());
+
+    let conn_for_err = conn.clone();
+    let conn_for_send = conn.clone();
+    let task = io::write_all(output, preface_and_settings)
+        .map_err(move |err| {
+            error!(
+                "fail to write preface and initial SETTINGS on connection {}: {:?}",
+                base62::encode(conn_for_err.id),
+                err);
+        })
+        .and_then(move |(output, _buf)| {
+            start_send_coroutine(rx, output, conn_for_send);
+            Ok(())
+        });
+    tokio::spawn(task);
+
+    if let Some(interval) = cfg.keepalive_interval {
+        start_keepalive_coroutine(conn.clone(), interval, cfg.keepalive_timeout);
+    }
     Ok(conn)
 }
 
+/// Thin `TcpStream`-specific convenience wrapper around `connect`: sets
+/// `TCP_NODELAY` before delegating. See `handshake_tcp`.
+pub fn connect_tcp<F>(
+    cfg: Config,
+    tcp: TcpStream,
+    on_frame: F,
+) -> Result<Arc<Connection>, super::error::Error>
+where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
+    tcp.set_nodelay(true).unwrap();
+    connect(cfg, tcp, on_frame)
+}
+
+/// Performs the client side of a TLS handshake over `tcp` with `connector`
+/// against `domain`, then asserts the peer negotiated the `"h2"` ALPN
+/// protocol before writing the HTTP/2 preface via `connect`. Rejects with a
+/// connection-level error if ALPN negotiated anything else, including
+/// nothing at all. See `accept_tls` for the server side.
+pub fn connect_tls<F>(
+    cfg: Config,
+    connector: TlsConnector,
+    domain: DNSNameRef,
+    tcp: TcpStream,
+    on_frame: F,
+) -> impl Future<Item = Arc<Connection>, Error = super::error::Error>
+where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
+    tcp.set_nodelay(true).unwrap();
+    connector.connect(domain, tcp)
+        .map_err(|err| Error::new(
+            error::Level::ConnectionLevel,
+            error::Code::ConnectError,
+            format!("TLS connect failed: {:?}", err)))
+        .and_then(move |tls| {
+            require_h2_alpn(&tls).and_then(|()| connect(cfg, tls, on_frame))
+        })
+}
+
+/// Shared by `accept_tls` and `connect_tls`: RFC 7540 §3.3 requires the
+/// `"h2"` ALPN protocol ID to be negotiated before an HTTP/2 connection over
+/// TLS may proceed.
+fn require_h2_alpn<IO, Se: rustls::Session>(
+    tls: &tokio_rustls::TlsStream<IO, Se>,
+) -> Result<(), super::error::Error> {
+    match tls.get_ref().1.get_alpn_protocol() {
+        Some(proto) if proto == b"h2" => Ok(()),
+        other => Err(Error::new(
+            error::Level::ConnectionLevel,
+            error::Code::ConnectError,
+            format!("expected ALPN protocol \"h2\", negotiated {:?}", other))),
+    }
+}
+
+/// Initiates an application-requested graceful shutdown (RFC 7540 §6.8):
+/// sends a GOAWAY naming the highest peer-initiated stream id processed so
+/// far and enters the draining state, so `receive_coroutine_continuation`
+/// stops dispatching newly opened streams to `on_frame` — but, unlike
+/// `go_away`, does not close the connection as soon as that GOAWAY reaches
+/// the wire. Streams already open keep being serviced until `drain` has
+/// elapsed, at which point `to_close` is finally set and the send coroutine
+/// winds down once its queue drains.
+pub fn disconnect(conn: Arc<Connection>, drain: Duration) {
+    conn.graceful_drain.store(true, Ordering::Release);
+    conn.go_away(error::Code::NoError, vec!());
+    let wakeup = Instant::now() + drain;
+    let task = tokio::timer::Delay::new(wakeup)
+        .map_err(|e| panic!("timer failed; err={:?}", e))
+        .and_then(move |_| {
+            info!(
+                "Close connection {} now that its {:?} disconnect() grace period has elapsed",
+                base62::encode(conn.id),
+                drain);
+            conn.to_close.store(true, Ordering::Release);
+            Ok(())
+        });
+    tokio::spawn(task);
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub sender_queue_size: usize,
     pub my_h2_settings: Vec<(SettingKey, u32)>,
+    /// Whether outgoing literal header field values are Huffman-coded.
+    /// Huffman coding trades CPU for smaller frames; some deployments want
+    /// to disable it.
+    pub use_huffman: bool,
+    /// When set, a PING is sent after this much idle time and the
+    /// round-trip is reported back through `Connection::last_ping_rtt`. If
+    /// the matching ACK does not arrive within `keepalive_timeout`, the
+    /// connection is closed with `go_away`.
+    pub keepalive_interval: Option<Duration>,
+    /// How long to wait for a keepalive PING's ACK before giving up on the
+    /// peer. Only consulted when `keepalive_interval` is set.
+    pub keepalive_timeout: Duration,
+}
+
+/// Which side of a connection this end is. `handshake` always produces a
+/// `Server` connection and `connect` always produces a `Client` one. RFC
+/// 7540 §5.1.1 ties this to stream id parity (client-initiated streams are
+/// odd, server-initiated ones even) and §6.8 ties it to GOAWAY semantics
+/// (only a client reconnects and retries on a GOAWAY whose last stream id
+/// it hasn't reached yet); callers needing either can branch on `role()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
 }
 
 pub struct Connection {
     id: u64,
+    role: Role,
     on_frame: FnBox,
-    sender: Sender<Frame>,
+    sender: FrameSender,
     my_h2_settings: Mutex<Settings>,
     remote_h2_settings: Mutex<Settings>,
     to_close: AtomicBool,
+    draining: AtomicBool,
+    /// Set by `disconnect` before its GOAWAY is sent, so `start_send_coroutine`
+    /// knows to leave `to_close` alone for that particular GOAWAY instead of
+    /// applying its usual close-as-soon-as-sent rule: `disconnect`'s own
+    /// drain timer is what sets `to_close`, once `drain` has elapsed.
+    graceful_drain: AtomicBool,
     last_received_stream_id: AtomicU32,
+    flow_control: FlowController,
+    /// Outgoing DATA frames `dispatch_outgoing_frame` has already dequeued
+    /// but couldn't afford to write, keyed by stream id and kept in arrival
+    /// order per stream so `release_pending_data` never reorders one
+    /// stream's DATA relative to itself. See `park_if_blocked`.
+    pending_data: Mutex<HashMap<u32, VecDeque<DataFrame>>>,
+    priority: PriorityTree,
+    header_encoder: Mutex<hpack::Encoder>,
+    pub header_decoder: Mutex<hpack::Decoder>,
+    /// Bytes of an in-progress header block (a HEADERS/PUSH_PROMISE not yet
+    /// terminated by the END_HEADERS flag), stashed here until the
+    /// CONTINUATION frame(s) completing it arrive. See `Frame::parse`.
+    pub(crate) pending_header_block: Mutex<Option<RawHeaderFragment>>,
+    outstanding_pings: Mutex<HashMap<u64, Instant>>,
+    last_ping_rtt: Mutex<Option<Duration>>,
+    last_activity: Mutex<Instant>,
 }
 
 struct FnBox(Box<dyn Fn(Arc<Connection>, Frame) -> ()>);
@@ -55,16 +288,34 @@ impl FnBox {
 }
 
 impl Connection {
-    fn new<F>(on_frame: F, sender: Sender<Frame>) -> Arc<Connection>
+    pub(crate) fn new<F>(role: Role, on_frame: F, sender: Sender<Frame>) -> Arc<Connection>
     where F: 'static + Sync + Send + Fn(Arc<Connection>, Frame) -> () {
         Arc::new(Connection{
             id: random::default().read_u64(),
+            role,
             on_frame: FnBox::new(on_frame),
-            sender,
+            sender: FrameSender::new(sender),
             my_h2_settings: Mutex::new(Settings::new()),
             remote_h2_settings: Mutex::new(Settings::new()),
             to_close: AtomicBool::new(false),
-            last_received_stream_id: AtomicU32::new(0)})
+            draining: AtomicBool::new(false),
+            graceful_drain: AtomicBool::new(false),
+            last_received_stream_id: AtomicU32::new(0),
+            flow_control: FlowController::new(
+                Settings::new().get(SettingKey::InitialWindowSize)),
+            pending_data: Mutex::new(HashMap::new()),
+            priority: PriorityTree::new(),
+            header_encoder: Mutex::new(
+                hpack::Encoder::with_capacity(
+                    Settings::new().get(SettingKey::HeaderTableSize) as usize)),
+            header_decoder: Mutex::new(
+                hpack::Decoder::with_capacity(
+                    Settings::new().get(SettingKey::HeaderTableSize) as usize)),
+            pending_header_block: Mutex::new(None),
+            outstanding_pings: Mutex::new(HashMap::new()),
+            last_ping_rtt: Mutex::new(None),
+            last_activity: Mutex::new(Instant::now()),
+        })
     }
 
     pub fn update_sender_h2_settings(
@@ -77,32 +328,310 @@ impl Connection {
                 whole.set(key.clone(), *val);
             }
         }
+        apply_own_initial_window_size(&self.flow_control, &new_values);
         let f = Frame::Settings(SettingsFrame::new(false, new_values));
         send_frame(self.sender.clone(), f);
     }
 
-    pub fn disconnect(&mut self) {
-        
+    /// Whether this end originated the connection (`connect`) or accepted it
+    /// (`handshake`). See `Role`.
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Emits a GOAWAY naming the highest peer-initiated stream id processed
+    /// so far, and moves the connection into a draining state that refuses
+    /// any further HEADERS (no new streams). Closes as soon as that GOAWAY
+    /// reaches the wire — for a shutdown that gives already-open streams a
+    /// chance to finish first, use `disconnect` instead.
+    pub fn go_away(&self, error_code: error::Code, debug_data: Vec<u8>) {
+        self.draining.store(true, Ordering::Release);
+        let f = GoAwayFrame{
+            last_stream_id: self.last_received_stream_id.load(Ordering::Acquire),
+            error_code,
+            debug_info: debug_data,
+        };
+        send_frame(self.sender.clone(), Frame::GoAway(f));
+    }
+
+    /// Whether `go_away` has been called locally, or a GOAWAY has been
+    /// received from the peer. Once draining, no further HEADERS frames are
+    /// dispatched to the `on_frame` callback.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Sends a PING carrying `payload` and records the send time so the
+    /// round-trip can be measured once the matching ACK arrives; see
+    /// `last_ping_rtt`. Used both for application-initiated liveness checks
+    /// and by the `keepalive_interval` timer.
+    pub fn ping(&self, payload: u64) {
+        self.outstanding_pings.lock().unwrap().insert(payload, Instant::now());
+        send_frame(self.sender.clone(), Frame::Ping(PingFrame::new(payload, false)));
+    }
+
+    /// The round-trip time measured from the most recently acknowledged
+    /// PING this connection sent, whether sent via `ping` or the keepalive
+    /// timer.
+    pub fn last_ping_rtt(&self) -> Option<Duration> {
+        *self.last_ping_rtt.lock().unwrap()
+    }
+
+    /// Connection- and stream-level send and receive windows, seeded from
+    /// the peer's `SETTINGS_INITIAL_WINDOW_SIZE`. Outgoing DATA frames must
+    /// check `available()` and `consume()` the bytes they send; incoming
+    /// DATA, WINDOW_UPDATE, and SETTINGS frames are wired to update it in
+    /// `receive_coroutine_continuation` and `read_settings`.
+    pub fn flow_control(&self) -> &FlowController {
+        &self.flow_control
+    }
+
+    /// The encoder-side HPACK dynamic table and Huffman policy for this
+    /// connection's outgoing header blocks. A peer's
+    /// `SETTINGS_HEADER_TABLE_SIZE` change is applied to it via
+    /// `apply_header_table_size_change`, wired into `read_settings` and
+    /// `receive_coroutine_continuation`.
+    pub fn header_encoder(&self) -> &Mutex<hpack::Encoder> {
+        &self.header_encoder
+    }
+
+    /// This connection's RFC 7540 §5.3 stream dependency tree. PRIORITY
+    /// frames and the priority block of a HEADERS frame are wired to
+    /// reparent it in `receive_coroutine_continuation`; `release_pending_data`
+    /// consults `next_to_send` to decide, among streams with parked DATA that
+    /// can now afford to send, which one goes next.
+    pub fn priority(&self) -> &PriorityTree {
+        &self.priority
+    }
+
+    /// Enqueues `f` for `start_send_coroutine` to write, same as the
+    /// internal `send_frame` helper every other frame-emitting method on
+    /// this type already funnels through, but returns a future for callers
+    /// that want to await the moment it lands in that queue rather than
+    /// fire-and-forget it.
+    pub fn send(&self, f: Frame) -> impl Future<Item = (), Error = ()> {
+        send_frame(self.sender.clone(), f);
+        future::ok(())
+    }
+}
+
+/// Applies every (key, value) pair from a peer's SETTINGS frame through the
+/// validating `Settings::apply` path, stopping at the first one that falls
+/// outside its RFC 7540 §6.5.2 range.
+fn apply_remote_settings(
+    whole: &mut Settings,
+    values: &Vec<(SettingKey, u32)>,
+) -> Result<(), SettingsError> {
+    for (key, val) in values {
+        whole.apply(key.clone(), *val)?;
     }
+    Ok(())
 }
 
-fn send_frame(mut q: Sender<Frame>, f: Frame) {
-    let res = q.try_send(f);
-    match res {
-        Ok(_) => (),
-        Err(err) => {
-            let f = err.into_inner();
-            let mut rng = random::default();
-            let delay = Duration::from_millis(rng.read_u64() % 30);
-            let wakeup = Instant::now() + delay;
-            let task = tokio::timer::Delay::new(wakeup)
-                .map_err(|e| panic!("timer failed; err={:?}", e))
-                .and_then(move |_| {
-                    send_frame(q, f);
+/// If a just-applied SETTINGS frame changed `SETTINGS_INITIAL_WINDOW_SIZE`,
+/// shifts every existing stream's send window by the signed delta (RFC 7540
+/// §6.9.2). `values` has already passed `apply_remote_settings`. Returns the
+/// `FlowControlError` from `FlowController::on_initial_window_size_change`
+/// unapplied if the shift would push any stream's window out of range.
+fn apply_initial_window_size_change(
+    conn: &Arc<Connection>,
+    values: &Vec<(SettingKey, u32)>,
+) -> Result<(), error::Code> {
+    for (key, val) in values {
+        if *key == SettingKey::InitialWindowSize {
+            conn.flow_control.on_initial_window_size_change(*val)?;
+        }
+    }
+    Ok(())
+}
+
+/// If our own outgoing SETTINGS sets `SETTINGS_INITIAL_WINDOW_SIZE`, seeds
+/// `flow_control`'s receive-side initial window from it. Unlike
+/// `apply_initial_window_size_change`, this only ever runs once, before any
+/// streams exist (`update_sender_h2_settings` is called right after
+/// `Connection::new`, before the connection is handed out), so there are no
+/// existing streams to shift by a delta.
+fn apply_own_initial_window_size(
+    flow_control: &FlowController,
+    values: &Vec<(SettingKey, u32)>,
+) {
+    for (key, val) in values {
+        if *key == SettingKey::InitialWindowSize {
+            flow_control.set_recv_initial_window_size(*val);
+        }
+    }
+}
+
+/// If a just-applied SETTINGS frame changed `SETTINGS_HEADER_TABLE_SIZE`,
+/// evicts this connection's encoder-side dynamic table down to the new
+/// limit and queues the mandatory Dynamic Table Size Update instruction
+/// (RFC 7541 §6.3) to be emitted at the start of the next outgoing header
+/// block.
+fn apply_header_table_size_change(conn: &Arc<Connection>, values: &Vec<(SettingKey, u32)>) {
+    for (key, val) in values {
+        if *key == SettingKey::HeaderTableSize {
+            conn.header_encoder.lock().unwrap().resize(*val as usize);
+        }
+    }
+}
+
+/// Once this connection has consumed at least half of its advertised
+/// connection-level receive window without a replenishing WINDOW_UPDATE,
+/// sends one for everything consumed so far (RFC 7540 §6.9): waiting for
+/// the window to empty out entirely before replenishing would stall a fast
+/// sender once less than one more DATA frame's worth of window remains.
+/// See `maybe_send_stream_window_update` for the per-stream equivalent,
+/// which must run alongside this one or a stream's DATA stalls once its own
+/// initial window is exhausted even though the connection window is fine.
+fn maybe_send_connection_window_update(conn: &Arc<Connection>) {
+    let initial = conn.flow_control.recv_initial_window_size();
+    let available = conn.flow_control.connection_recv_available();
+    let consumed = initial - available;
+    if consumed > 0 && consumed >= initial / 2 {
+        conn.flow_control.replenish_connection_recv_window(consumed as u32);
+        send_frame(conn.sender.clone(), Frame::WindowUpdate(WindowUpdateFrame::new(0, consumed as u32)));
+    }
+}
+
+/// The per-stream analogue of `maybe_send_connection_window_update`: once
+/// `stream_id` has consumed at least half of its own advertised receive
+/// window, sends a stream-scoped WINDOW_UPDATE for everything consumed on
+/// it so far. Without this, a compliant peer caps that one stream's DATA at
+/// its initial window (65535 bytes by default) and stalls forever once it's
+/// exhausted, even though the connection-level window still has room —
+/// this is the only place an outgoing stream-level WINDOW_UPDATE is built.
+fn maybe_send_stream_window_update(conn: &Arc<Connection>, stream_id: u32) {
+    let initial = conn.flow_control.recv_initial_window_size();
+    let available = conn.flow_control.stream_recv_available(stream_id);
+    let consumed = initial - available;
+    if consumed > 0 && consumed >= initial / 2 {
+        conn.flow_control.replenish_stream_recv_window(stream_id, consumed as u32);
+        send_frame(conn.sender.clone(), Frame::WindowUpdate(WindowUpdateFrame::new(stream_id, consumed as u32)));
+    }
+}
+
+/// Rejects a connection-level WINDOW_UPDATE violation (stream id 0, zero
+/// increment, or an increment pushing the window out of range): sends a
+/// GOAWAY carrying `code` and marks the connection for close, mirroring
+/// `reject_remote_settings`.
+fn reject_window_update(conn: &Arc<Connection>, code: error::Code) -> Error {
+    info!(
+        "Close connection {} because of an out-of-range WINDOW_UPDATE",
+        base62::encode(conn.id));
+    let f = GoAwayFrame{
+        last_stream_id: conn.last_received_stream_id.load(Ordering::Acquire),
+        error_code: code.clone(),
+        debug_info: vec!()};
+    send_frame(conn.sender.clone(), Frame::GoAway(f));
+    conn.to_close.store(true, Ordering::Release);
+    Error::new(
+        error::Level::ConnectionLevel,
+        code,
+        "WINDOW_UPDATE increment out of range".to_string())
+}
+
+/// Answers a stream-scoped WINDOW_UPDATE violation (a zero increment or an
+/// out-of-range increment on a single stream) the way RFC 7540 §6.9.1
+/// requires: reset that one stream with RST_STREAM rather than tearing down
+/// the whole connection for one stream's fault.
+fn reject_stream_window_update(conn: &Arc<Connection>, stream_id: u32, code: error::Code) {
+    info!(
+        "connection {}: resetting stream {} for an invalid WINDOW_UPDATE ({:?})",
+        base62::encode(conn.id),
+        stream_id,
+        code);
+    send_frame(conn.sender.clone(), Frame::RstStream(RstStreamFrame::new(stream_id, code)));
+}
+
+/// Rejects a peer's SETTINGS frame: sends a GOAWAY carrying the error's code
+/// and marks the connection for close, mirroring how other fatal read/write
+/// errors on this connection are handled.
+fn reject_remote_settings(conn: &Arc<Connection>, err: SettingsError) -> Error {
+    let message = format!("{}", err);
+    info!(
+        "Close connection {} because of invalid SETTINGS value: {}",
+        base62::encode(conn.id),
+        message);
+    let f = GoAwayFrame{
+        last_stream_id: conn.last_received_stream_id.load(Ordering::Acquire),
+        error_code: err.code.clone(),
+        debug_info: vec!()};
+    send_frame(conn.sender.clone(), Frame::GoAway(f));
+    conn.to_close.store(true, Ordering::Release);
+    Error::new(error::Level::ConnectionLevel, err.code, message)
+}
+
+/// A clonable handle onto a connection's outgoing-frame queue. Wraps the
+/// bounded channel `start_send_coroutine` reads from with a FIFO buffer and
+/// a single drain task (see `send_frame`), so any number of callers —
+/// spread across however many tasks end up touching this connection — can
+/// hand it a `Frame` without racing each other for the wire.
+#[derive(Clone)]
+struct FrameSender {
+    inner: Arc<FrameSenderInner>,
+}
+
+struct FrameSenderInner {
+    wire: Sender<Frame>,
+    queue: Mutex<VecDeque<Frame>>,
+    draining: AtomicBool,
+}
+
+impl FrameSender {
+    fn new(wire: Sender<Frame>) -> FrameSender {
+        FrameSender{
+            inner: Arc::new(FrameSenderInner{
+                wire,
+                queue: Mutex::new(VecDeque::new()),
+                draining: AtomicBool::new(false),
+            }),
+        }
+    }
+}
+
+/// Queues `f` for `start_send_coroutine` to write, and, if no drain task is
+/// already running for this connection, spawns one. Unlike the old
+/// random-jitter `try_send` retry — where a frame that found the channel
+/// full was handed to an independently-delayed, independently-spawned retry
+/// task, letting it race a later call's frame and land out of order on the
+/// wire — every frame passed to `send_frame` for a given connection is
+/// pushed onto one FIFO queue and written by one drain task at a time, so
+/// ordering is preserved and a full channel parks that task instead of
+/// spinning.
+fn send_frame(handle: FrameSender, f: Frame) {
+    handle.inner.queue.lock().unwrap().push_back(f);
+    if !handle.inner.draining.swap(true, Ordering::AcqRel) {
+        drain_frame_queue(handle);
+    }
+}
+
+/// The single drain task `send_frame` spawns (and respawns, one write at a
+/// time) for as long as its connection's queue keeps being non-empty.
+fn drain_frame_queue(handle: FrameSender) {
+    let next = handle.inner.queue.lock().unwrap().pop_front();
+    match next {
+        None => {
+            handle.inner.draining.store(false, Ordering::Release);
+            // A push can have landed between the `pop_front` above
+            // returning `None` and clearing `draining`; if so, make sure
+            // its frame still gets a drainer.
+            let still_pending = !handle.inner.queue.lock().unwrap().is_empty();
+            if still_pending && !handle.inner.draining.swap(true, Ordering::AcqRel) {
+                drain_frame_queue(handle);
+            }
+        },
+        Some(f) => {
+            let handle2 = handle.clone();
+            let task = handle.inner.wire.clone().send(f)
+                .map_err(|err| {
+                    error!("failed to write a queued frame to the send channel: {:?}", err);
+                })
+                .and_then(move |_wire| {
+                    drain_frame_queue(handle2);
                     Ok(())
                 });
             tokio::spawn(task);
-        }
+        },
     }
 }
 
@@ -127,6 +656,26 @@ where R: 'static + Send + AsyncRead {
     tokio::spawn(task);
 }
 
+/// The client-side counterpart to `start_receive_coroutine`: a client does
+/// not read a preface (it writes one instead, in `connect`), so this skips
+/// straight to the peer's initial SETTINGS frame before joining the same
+/// `receive_coroutine_continuation` loop the server side uses.
+fn start_receive_coroutine_from_settings<R>(
+    socket_in: R,
+    conn: Arc<Connection>,
+) -> ()
+where R: 'static + Send + AsyncRead {
+    let task = read_settings(socket_in, conn)
+        .and_then(|(socket_in, conn)| {
+            receive_coroutine_continuation(socket_in, conn);
+            Ok(())
+        })
+        .map_err(|err| {
+            error!("Read error: {:?}", err);
+        });
+    tokio::spawn(task);
+}
+
 fn receive_coroutine_continuation<R>(
     socket_in: R,
     conn: Arc<Connection>,
@@ -138,14 +687,22 @@ where R: 'static + Send + AsyncRead {
     let conn1 = conn.clone();
     let task = read_frame(socket_in, conn)
         .and_then(|(socket_in, conn, frame)| {
+            *conn.last_activity.lock().unwrap() = Instant::now();
             match frame {
                 Frame::Settings(ref f) => {
                     if !f.ack {
                         debug!("ack a SETTINGS_FRAME");
-                        let whole: &mut Settings = &mut conn.remote_h2_settings.lock().unwrap();
-                        for (key, val) in &f.values {
-                            whole.set(key.clone(), *val);
+                        let result = {
+                            let whole: &mut Settings = &mut conn.remote_h2_settings.lock().unwrap();
+                            apply_remote_settings(whole, &f.values)
+                        };
+                        if let Err(err) = result {
+                            return Err(reject_remote_settings(&conn, err));
                         }
+                        if let Err(code) = apply_initial_window_size_change(&conn, &f.values) {
+                            return Err(reject_window_update(&conn, code));
+                        }
+                        apply_header_table_size_change(&conn, &f.values);
                         send_frame(conn.sender.clone(), Frame::Settings(SettingsFrame::new(true, vec!())));
                     }
                 },
@@ -154,12 +711,73 @@ where R: 'static + Send + AsyncRead {
                         "Close connection {} because of receiving GoAway frame: {:?}",
                         base62::encode(conn.id),
                         f);
+                    conn.draining.store(true, Ordering::Release);
                     let f = GoAwayFrame{
                         last_stream_id: conn.last_received_stream_id.load(Ordering::Acquire),
-                        error_code: ErrorCode::NoError,
+                        error_code: error::Code::NoError,
                         debug_info: vec!()};
                     send_frame(conn.sender.clone(), Frame::GoAway(f));
                 },
+                Frame::WindowUpdate(ref f) => {
+                    match conn.flow_control.on_window_update(f.stream_id, f.increment) {
+                        Ok(()) => release_pending_data(&conn),
+                        Err(WindowUpdateError::Connection(code)) => {
+                            return Err(reject_window_update(&conn, code));
+                        },
+                        Err(WindowUpdateError::Stream(stream_id, code)) => {
+                            reject_stream_window_update(&conn, stream_id, code);
+                        },
+                    }
+                },
+                Frame::Data(ref f) => {
+                    conn.flow_control.on_data_received(f.stream_id, f.data.len() as u32);
+                    maybe_send_connection_window_update(&conn);
+                    maybe_send_stream_window_update(&conn, f.stream_id);
+                },
+                Frame::Ping(ref f) => {
+                    if f.ack {
+                        if let Some(sent_at) = conn.outstanding_pings.lock().unwrap().remove(&f.opaque) {
+                            *conn.last_ping_rtt.lock().unwrap() = Some(sent_at.elapsed());
+                        }
+                    } else {
+                        // the send queue has no priority lanes yet, so this
+                        // goes through the same path as every other control
+                        // frame rather than actually jumping the line.
+                        send_frame(conn.sender.clone(), Frame::Ping(PingFrame::new(f.opaque, true)));
+                    }
+                },
+                Frame::Priority(ref f) => {
+                    if let Err(err) = conn.priority.apply(f.my_stream_id, f.dep_stream_id, f.exclusive, f.weight) {
+                        return Err(err);
+                    }
+                },
+                Frame::Headers(ref f) if !f.end_headers => {
+                    // a HEADERS or CONTINUATION fragment whose block isn't
+                    // finished yet; `Frame::parse` has already stashed its
+                    // bytes on `conn.pending_header_block` and handed back
+                    // this placeholder purely so the read loop has a `Frame`
+                    // to match on. Nothing to dispatch until END_HEADERS.
+                    receive_coroutine_continuation(socket_in, conn);
+                    return Ok(());
+                },
+                Frame::Headers(_) if conn.draining.load(Ordering::Acquire) => {
+                    debug!(
+                        "Connection {} is draining; dropping a HEADERS frame instead of dispatching it.",
+                        base62::encode(conn.id));
+                    receive_coroutine_continuation(socket_in, conn);
+                    return Ok(());
+                },
+                Frame::Headers(ref f) => {
+                    let peer_initiated = (f.stream_id % 2 == 1) == (conn.role == Role::Server);
+                    if peer_initiated && f.stream_id > conn.last_received_stream_id.load(Ordering::Acquire) {
+                        conn.last_received_stream_id.store(f.stream_id, Ordering::Release);
+                    }
+                    if let Some(ref p) = f.priority {
+                        if let Err(err) = conn.priority.apply(f.stream_id, p.dependency_stream, p.exclusive, p.weight) {
+                            return Err(err);
+                        }
+                    }
+                },
                 _ => (),
             }
             {
@@ -170,13 +788,23 @@ where R: 'static + Send + AsyncRead {
             Ok(())
         })
         .map_err(move |err| {
+            if let Some(rst) = RstStreamFrame::from_stream_error(&err) {
+                info!(
+                    "Resetting stream {} on connection {} instead of closing the whole \
+                     connection: {:?}",
+                    rst.stream_id,
+                    base62::encode(conn1.id),
+                    err);
+                send_frame(conn1.sender.clone(), Frame::RstStream(rst));
+                return;
+            }
             error!(
                 "Close connection {} because of reading error: {:?}",
                 base62::encode(conn1.id),
                 err);
             let f = GoAwayFrame{
                 last_stream_id: conn1.last_received_stream_id.load(Ordering::Acquire),
-                error_code: ErrorCode::ConnectError,
+                error_code: error::Code::ConnectError,
                 debug_info: vec!()};
             send_frame(conn1.sender.clone(), Frame::GoAway(f));
         });
@@ -194,8 +822,8 @@ fn read_preface<R: 'static + Send + AsyncRead>(
                 Err(err) => {
                     error!("fail to read HTTP/2 preface: {:?}", err);
                     return Err(Error::new(
-                        ErrorLevel::ConnectionLevel,
-                        ErrorCode::ProtocolError,
+                        error::Level::ConnectionLevel,
+                        error::Code::ProtocolError,
                         "fail to read HTTP/2 preface".to_string()));
                 },
                 Ok((socket_in, buf)) => {
@@ -204,8 +832,8 @@ fn read_preface<R: 'static + Send + AsyncRead>(
                                PREFACE.as_bytes(),
                                buf);
                         return Err(Error::new(
-                            ErrorLevel::ConnectionLevel,
-                            ErrorCode::ProtocolError,
+                            error::Level::ConnectionLevel,
+                            error::Code::ProtocolError,
                             "HTTP/2 preface mismatch".to_string()));
                     } else {
                         debug!("read HTTP/2 preface");
@@ -225,10 +853,17 @@ fn read_settings<R: 'static + Send + AsyncRead>(
             match frame {
                 Frame::Settings(ref f) => {
                     debug!("ack a SETTINGS_FRAME");
-                    let whole: &mut Settings = &mut conn.remote_h2_settings.lock().unwrap();
-                    for (key, val) in &f.values {
-                        whole.set(key.clone(), *val);
+                    let result = {
+                        let whole: &mut Settings = &mut conn.remote_h2_settings.lock().unwrap();
+                        apply_remote_settings(whole, &f.values)
+                    };
+                    if let Err(err) = result {
+                        return Err(reject_remote_settings(&conn, err));
+                    }
+                    if let Err(code) = apply_initial_window_size_change(&conn, &f.values) {
+                        return Err(reject_window_update(&conn, code));
                     }
+                    apply_header_table_size_change(&conn, &f.values);
                     send_frame(conn.sender.clone(), Frame::Settings(SettingsFrame::new(true, vec!())));
                 },
                 _ => {unreachable!()},
@@ -241,47 +876,118 @@ fn read_settings<R: 'static + Send + AsyncRead>(
         })
 }
 
+/// HEADERS and CONTINUATION frame types (RFC 7540 §6.2, §6.10): the only
+/// frame types whose body is (part of) a header block, and so the only ones
+/// `read_frame` checks against `SETTINGS_MAX_HEADER_LIST_SIZE`.
+const FRAME_TYPE_HEADERS: u8 = 1;
+const FRAME_TYPE_CONTINUATION: u8 = 9;
+
 fn read_frame<R: 'static + Send + AsyncRead>(
     socket_in: R,
     conn: Arc<Connection>,
-) -> impl Future<Item = (R, Arc<Connection>, Frame), Error = Error> {
+) -> Box<dyn Future<Item = (R, Arc<Connection>, Frame), Error = Error> + Send> {
     let buf = [0u8; 9];
     let conn1 = conn.clone();
-    io::read_exact(socket_in, buf)
+    Box::new(io::read_exact(socket_in, buf)
         .map_err(move |err| {
             info!("fail to read connection {} because {:?}",
                   base62::encode(conn1.id),
                   err);
             Error::new(
-                error::ErrorLevel::ConnectionLevel,
-                error::ErrorCode::ConnectError,
+                error::Level::ConnectionLevel,
+                error::Code::ConnectError,
                 format!("fail to read on connection {}", base62::encode(conn1.id)))
         })
-        .and_then(|(socket_in, buf)| {
+        .and_then(move |(socket_in, buf)| {
             let buf: &[u8] = &buf;
             let frame_header = FrameHeader::parse(buf);
+
+            // Check the declared body length against our own advertised
+            // limits *before* allocating a buffer for it, so a peer can't
+            // force an unbounded allocation just by lying about body_len.
+            let max_frame_size = conn.my_h2_settings.lock().unwrap().get(SettingKey::MaxFrameSize);
+            if frame_header.body_len > max_frame_size as usize {
+                return Box::new(future::err(reject_oversized_frame(&conn, frame_header.body_len, max_frame_size)))
+                    as Box<dyn Future<Item = (R, Arc<Connection>, Frame), Error = Error> + Send>;
+            }
+            if frame_header.frame_type == FRAME_TYPE_HEADERS || frame_header.frame_type == FRAME_TYPE_CONTINUATION {
+                let max_header_list_size = conn.my_h2_settings.lock().unwrap().get(SettingKey::MaxHeaderListSize);
+                if frame_header.body_len as u64 > max_header_list_size as u64 {
+                    return Box::new(future::err(reject_oversized_header_block(&conn, frame_header.body_len, max_header_list_size)))
+                        as Box<dyn Future<Item = (R, Arc<Connection>, Frame), Error = Error> + Send>;
+                }
+            }
+
             let mut body = Vec::<u8>::with_capacity(frame_header.body_len);
             body.resize(frame_header.body_len, 0);
             let conn1 = conn.clone();
-            io::read_exact(socket_in, body)
+            Box::new(io::read_exact(socket_in, body)
                 .map_err(move |err| {
                     info!("fail to read connection {} because {:?}",
                           base62::encode(conn1.id),
                           err);
                     Error::new(
-                        error::ErrorLevel::ConnectionLevel,
-                        error::ErrorCode::ConnectError,
+                        error::Level::ConnectionLevel,
+                        error::Code::ConnectError,
                         format!("fail to read on connection {}", conn1.id))
                 })
                 .and_then(move |(socket_in, body)| {
                     debug!("succeed to read payload of a frame with {} bytes", body.len());
-                    let frame = Frame::parse(&frame_header, body);
+                    let frame = Frame::parse(&conn, &frame_header, body);
                     match frame {
                         Ok(f) => Ok((socket_in, conn, f)),
                         Err(err) => Err(err),
                     }
-                })
-        })
+                }))
+                as Box<dyn Future<Item = (R, Arc<Connection>, Frame), Error = Error> + Send>
+        }))
+}
+
+/// Rejects a frame whose declared body length exceeds our advertised
+/// `SETTINGS_MAX_FRAME_SIZE` (RFC 7540 §4.2), ahead of ever allocating a
+/// buffer for it: sends a GOAWAY and marks the connection for close,
+/// mirroring `reject_remote_settings`.
+fn reject_oversized_frame(conn: &Arc<Connection>, body_len: usize, max_frame_size: u32) -> Error {
+    info!(
+        "Close connection {} because a frame declared a {}-byte body, exceeding our advertised SETTINGS_MAX_FRAME_SIZE of {}",
+        base62::encode(conn.id),
+        body_len,
+        max_frame_size);
+    let f = GoAwayFrame{
+        last_stream_id: conn.last_received_stream_id.load(Ordering::Acquire),
+        error_code: error::Code::FrameSizeError,
+        debug_info: vec!()};
+    send_frame(conn.sender.clone(), Frame::GoAway(f));
+    conn.to_close.store(true, Ordering::Release);
+    Error::new(
+        error::Level::ConnectionLevel,
+        error::Code::FrameSizeError,
+        format!("frame body of {} bytes exceeds SETTINGS_MAX_FRAME_SIZE of {}", body_len, max_frame_size))
+}
+
+/// Rejects a HEADERS/CONTINUATION frame whose declared body length exceeds
+/// our advertised `SETTINGS_MAX_HEADER_LIST_SIZE`, the same
+/// allocate-nothing rejection as `reject_oversized_frame`. This only bounds
+/// the wire size of one header block fragment, not the fully decompressed
+/// header list HPACK eventually produces, but it is a cheap check ahead of
+/// that decoding that still closes the most obvious memory-exhaustion
+/// vector.
+fn reject_oversized_header_block(conn: &Arc<Connection>, body_len: usize, max_header_list_size: u32) -> Error {
+    info!(
+        "Close connection {} because a header block fragment of {} bytes exceeds our advertised SETTINGS_MAX_HEADER_LIST_SIZE of {}",
+        base62::encode(conn.id),
+        body_len,
+        max_header_list_size);
+    let f = GoAwayFrame{
+        last_stream_id: conn.last_received_stream_id.load(Ordering::Acquire),
+        error_code: error::Code::FrameSizeError,
+        debug_info: vec!()};
+    send_frame(conn.sender.clone(), Frame::GoAway(f));
+    conn.to_close.store(true, Ordering::Release);
+    Error::new(
+        error::Level::ConnectionLevel,
+        error::Code::FrameSizeError,
+        format!("header block fragment of {} bytes exceeds SETTINGS_MAX_HEADER_LIST_SIZE of {}", body_len, max_header_list_size))
 }
 
 fn start_send_coroutine<W>(
@@ -299,33 +1005,9 @@ where W: 'static + Send + AsyncWrite {
             if conn.to_close.load(Ordering::Acquire) {
                 return Ok(());
             }
-            match frame {
-                None => (),
-                Some(frame) => {
-                    match frame {
-                        Frame::GoAway(_) => {
-                            conn.to_close.store(true, Ordering::Release);
-                        },
-                        _ => (),
-                    };
-                    debug!("dump a frame {:?}", frame);
-                    let buf = frame.serialize();
-                    let conn2 = conn.clone();
-                    let task = io::write_all(socket_out, buf)
-                        .and_then(|(socket_out, _buf)| {
-                            start_send_coroutine(rx, socket_out, conn);
-                            Ok(())
-                        })
-                        .map_err(move |err| {
-                            info!(
-                                "Close connection {} because of writing error: {:?}",
-                                base62::encode(conn2.id),
-                                err);
-                            conn2.to_close.store(true, Ordering::Release);
-                        });
-                    tokio::spawn(task);
-                }
-            };
+            if let Some(frame) = frame {
+                dispatch_outgoing_frame(frame, rx, socket_out, conn);
+            }
             Ok(())
         })
         .map_err(move |err| {
@@ -338,3 +1020,242 @@ where W: 'static + Send + AsyncWrite {
     tokio::spawn(task);
 }
 
+/// Writes one frame dequeued from `rx` and resumes `start_send_coroutine`'s
+/// loop with the rest — except a DATA frame that would drive
+/// `flow_control`'s send window negative (RFC 7540 §6.9), which is parked on
+/// `conn.pending_data` instead of being written. Parking it here rather than
+/// stalling this whole function on a retry timer (the approach `chunk8-5`
+/// replaced for the queue side, and which this used to reintroduce on the
+/// dequeue side) means every other frame still queued behind it — for other
+/// streams, or not subject to flow control at all — keeps draining; the
+/// parked frame is handed back to `send_frame` by `release_pending_data`
+/// once `receive_coroutine_continuation` sees the WINDOW_UPDATE that frees
+/// enough window for it.
+fn dispatch_outgoing_frame<W>(
+    frame: Frame,
+    rx: Receiver<Frame>,
+    socket_out: W,
+    conn: Arc<Connection>,
+) -> ()
+where W: 'static + Send + AsyncWrite {
+    let frame = match frame {
+        Frame::Data(f) => match park_if_blocked(&conn, f) {
+            Some(f) => Frame::Data(f),
+            None => {
+                start_send_coroutine(rx, socket_out, conn);
+                return;
+            },
+        },
+        other => other,
+    };
+    match frame {
+        Frame::GoAway(_) => {
+            // `disconnect`'s GOAWAY is exempt: its own drain timer is what
+            // sets `to_close`, once already-open streams have had `drain`
+            // to finish.
+            if !conn.graceful_drain.load(Ordering::Acquire) {
+                conn.to_close.store(true, Ordering::Release);
+            }
+        },
+        _ => (),
+    };
+    debug!("dump a frame {:?}", frame);
+    let segments = frame.serialize_segments();
+    let conn2 = conn.clone();
+    let task = write_all_vectored(socket_out, segments)
+        .and_then(|socket_out| {
+            start_send_coroutine(rx, socket_out, conn);
+            Ok(())
+        })
+        .map_err(move |err| {
+            info!(
+                "Close connection {} because of writing error: {:?}",
+                base62::encode(conn2.id),
+                err);
+            conn2.to_close.store(true, Ordering::Release);
+        });
+    tokio::spawn(task);
+}
+
+/// If `f` can be sent without driving its stream's send window negative,
+/// and no earlier DATA frame for that same stream is still parked ahead of
+/// it, consumes the window for it and hands it back to write. Otherwise
+/// appends it to `conn.pending_data` and returns `None` — checking for an
+/// already-parked predecessor (rather than just the window) is what keeps a
+/// later, smaller frame from jumping the queue and reordering that stream's
+/// DATA.
+fn park_if_blocked(conn: &Arc<Connection>, f: DataFrame) -> Option<DataFrame> {
+    let mut pending = conn.pending_data.lock().unwrap();
+    let already_blocked = pending.get(&f.stream_id).map_or(false, |q| !q.is_empty());
+    if already_blocked || conn.flow_control.available(f.stream_id) < f.data.len() as i64 {
+        pending.entry(f.stream_id).or_insert_with(VecDeque::new).push_back(f);
+        return None;
+    }
+    conn.flow_control.consume(f.stream_id, f.data.len() as u32);
+    Some(f)
+}
+
+/// Called once a WINDOW_UPDATE has been applied in
+/// `receive_coroutine_continuation`: re-enqueues every parked DATA frame
+/// whose stream can now afford it, via the same `send_frame` path any other
+/// outgoing frame takes, preserving each stream's parked order. Whether a
+/// frame is actually still affordable by the time it reaches the front of
+/// `send_frame`'s queue is rechecked there by `park_if_blocked`, so the
+/// `available` reads below only need to be a good-enough estimate of what
+/// this WINDOW_UPDATE just freed, not a reservation.
+///
+/// Among streams that can all afford to send right now, `conn.priority`'s
+/// `next_to_send` picks which one actually goes next, one frame at a time,
+/// so PRIORITY frames and HEADERS priority blocks have a real effect on
+/// write order instead of only being tracked.
+fn release_pending_data(conn: &Arc<Connection>) {
+    let mut pending = conn.pending_data.lock().unwrap();
+    let mut estimated_available: HashMap<u32, i64> = HashMap::new();
+    loop {
+        let ready: HashSet<u32> = pending.iter()
+            .filter_map(|(&stream_id, queue)| {
+                let available = *estimated_available.entry(stream_id)
+                    .or_insert_with(|| conn.flow_control.available(stream_id));
+                match queue.front() {
+                    Some(f) if available >= f.data.len() as i64 => Some(stream_id),
+                    _ => None,
+                }
+            })
+            .collect();
+        let stream_id = match conn.priority.next_to_send(&ready) {
+            Some(stream_id) => stream_id,
+            None => break,
+        };
+        let f = pending.get_mut(&stream_id).unwrap().pop_front().unwrap();
+        *estimated_available.get_mut(&stream_id).unwrap() -= f.data.len() as i64;
+        send_frame(conn.sender.clone(), Frame::Data(f));
+    }
+    pending.retain(|_, queue| !queue.is_empty());
+}
+
+/// Drives `Config::keepalive_interval`/`keepalive_timeout`: once this
+/// connection has been idle (no frame received) for `interval`, sends a
+/// PING and schedules a second wakeup `timeout` later to check whether the
+/// matching ACK arrived. If it didn't, the connection is considered dead
+/// and torn down via `go_away`; otherwise the idle timer restarts.
+fn start_keepalive_coroutine(conn: Arc<Connection>, interval: Duration, timeout: Duration) {
+    if conn.to_close.load(Ordering::Acquire) || conn.draining.load(Ordering::Acquire) {
+        return;
+    }
+    let wakeup = Instant::now() + interval;
+    let task = tokio::timer::Delay::new(wakeup)
+        .map_err(|e| panic!("timer failed; err={:?}", e))
+        .and_then(move |_| {
+            if conn.to_close.load(Ordering::Acquire) || conn.draining.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            let idle = conn.last_activity.lock().unwrap().elapsed();
+            if idle < interval {
+                start_keepalive_coroutine(conn, interval - idle, timeout);
+                return Ok(());
+            }
+            let payload = random::default().read_u64();
+            conn.ping(payload);
+            let conn2 = conn.clone();
+            let timeout_at = Instant::now() + timeout;
+            let timeout_task = tokio::timer::Delay::new(timeout_at)
+                .map_err(|e| panic!("timer failed; err={:?}", e))
+                .and_then(move |_| {
+                    let timed_out = conn2.outstanding_pings.lock().unwrap().contains_key(&payload);
+                    if timed_out {
+                        info!(
+                            "Close connection {} because a keepalive PING went unanswered",
+                            base62::encode(conn2.id));
+                        conn2.go_away(error::Code::ConnectError, b"keepalive PING timed out".to_vec());
+                    } else {
+                        start_keepalive_coroutine(conn2, interval, timeout);
+                    }
+                    Ok(())
+                });
+            tokio::spawn(timeout_task);
+            Ok(())
+        });
+    tokio::spawn(task);
+}
+
+/// Writes an ordered list of byte segments (e.g. a frame header followed by
+/// its payload) to `w`, issuing a single vectored write per poll instead of
+/// first concatenating everything into one buffer. Falls back to a plain
+/// `io::write_all` when there is only one segment, since there is nothing to
+/// gain from vectoring a single buffer.
+fn write_all_vectored<W>(
+    w: W,
+    mut segments: Vec<Vec<u8>>,
+) -> Box<dyn Future<Item = W, Error = std::io::Error> + Send>
+where W: 'static + Send + AsyncWrite {
+    if segments.len() <= 1 {
+        let buf = segments.pop().unwrap_or_else(|| vec!());
+        return Box::new(io::write_all(w, buf).map(|(w, _buf)| w));
+    }
+    Box::new(WriteAllVectored::new(w, segments))
+}
+
+struct WriteAllVectored<W> {
+    writer: Option<W>,
+    segments: VecDeque<Vec<u8>>,
+    offset: usize,
+}
+
+impl<W> WriteAllVectored<W> {
+    fn new(writer: W, segments: Vec<Vec<u8>>) -> WriteAllVectored<W> {
+        WriteAllVectored{writer: Some(writer), segments: segments.into(), offset: 0}
+    }
+}
+
+impl<W: AsyncWrite> Future for WriteAllVectored<W> {
+    type Item = W;
+    type Error = std::io::Error;
+
+    fn poll(&mut self) -> Poll<W, std::io::Error> {
+        while !self.segments.is_empty() {
+            let slices: Vec<IoSlice> = self.segments.iter().enumerate()
+                .map(|(i, seg)| {
+                    if i == 0 {
+                        IoSlice::new(&seg[self.offset..])
+                    } else {
+                        IoSlice::new(seg.as_slice())
+                    }
+                })
+                .collect();
+
+            let n = {
+                let writer = self.writer.as_mut()
+                    .expect("polled WriteAllVectored after completion");
+                match writer.write_vectored(&slices) {
+                    Ok(n) => n,
+                    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        return Ok(Async::NotReady);
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+
+            if n == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    "failed to write whole buffer"));
+            }
+
+            let mut remaining = n;
+            while remaining > 0 {
+                let front_len = self.segments.front().unwrap().len() - self.offset;
+                if remaining < front_len {
+                    self.offset += remaining;
+                    remaining = 0;
+                } else {
+                    remaining -= front_len;
+                    self.segments.pop_front();
+                    self.offset = 0;
+                }
+            }
+        }
+
+        Ok(Async::Ready(self.writer.take().expect("poll past completion")))
+    }
+}
+
@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use super::error;
+
+/// RFC 7540 §5.3.5: a stream with no explicit PRIORITY/HEADERS-priority
+/// depends on stream 0 with this weight.
+const DEFAULT_WEIGHT: u16 = 16;
+
+struct Node {
+    parent: u32,
+    weight: u16,
+    children: Vec<u32>,
+    /// Nginx-style smooth weighted round-robin slider: bumped by `weight`
+    /// every time this node is considered for selection among its current
+    /// siblings, and knocked back down by the siblings' combined weight once
+    /// picked. Lets `next_to_send` interleave proportionally to weight
+    /// instead of bursting through one sibling's whole share before moving
+    /// on.
+    current_weight: i64,
+}
+
+impl Node {
+    fn new(parent: u32, weight: u16) -> Node {
+        Node{parent, weight, children: vec!(), current_weight: 0}
+    }
+}
+
+/// RFC 7540 §5.3's stream dependency tree, plus the weighted round-robin
+/// scheduler it exists to drive. Every stream is a node depending on another
+/// stream (stream 0, the root, if never given an explicit dependency);
+/// `apply` reparents a stream when a PRIORITY frame or a HEADERS frame's
+/// priority block arrives, and `next_to_send` walks the tree to pick which
+/// stream with buffered DATA should be allowed to send next.
+pub struct PriorityTree {
+    nodes: Mutex<HashMap<u32, Node>>,
+}
+
+impl PriorityTree {
+    pub fn new() -> PriorityTree {
+        let mut nodes = HashMap::new();
+        nodes.insert(0, Node::new(0, DEFAULT_WEIGHT));
+        PriorityTree{nodes: Mutex::new(nodes)}
+    }
+
+    /// Reparents `stream_id` to depend on `dependency` with `weight`
+    /// (1..=256), honoring the exclusive flag (RFC 7540 §5.3.1): if
+    /// `exclusive`, every stream that already depended on `dependency` is
+    /// moved to depend on `stream_id` instead. Either id may not have been
+    /// seen before (e.g. a PRIORITY frame arriving ahead of that stream's
+    /// HEADERS); an idle placeholder node depending on the root is created
+    /// for it first. A stream naming itself as its own dependency is a
+    /// PROTOCOL_ERROR (RFC 7540 §5.3.1).
+    pub fn apply(
+        &self,
+        stream_id: u32,
+        dependency: u32,
+        exclusive: bool,
+        weight: u16,
+    ) -> Result<(), error::Error> {
+        if stream_id == dependency {
+            return Err(error::Error::new_for_stream(
+                error::Code::ProtocolError,
+                stream_id,
+                format!("stream {} cannot depend on itself", stream_id)));
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        ensure_node(&mut nodes, dependency);
+        ensure_node(&mut nodes, stream_id);
+
+        let old_parent = nodes[&stream_id].parent;
+
+        // RFC 7540 §5.3.3: if `dependency` is already one of `stream_id`'s
+        // own dependencies (i.e. a descendant of it), reparenting
+        // `stream_id` onto it as-is would detach that whole subtree from
+        // the tree into an orphaned cycle. Move `dependency` to depend on
+        // `stream_id`'s former parent first, keeping its own weight, so it
+        // (and everything under it) stays reachable from the root.
+        if is_descendant(&nodes, stream_id, dependency) {
+            let dependency_old_parent = nodes[&dependency].parent;
+            nodes.get_mut(&dependency_old_parent).unwrap().children.retain(|&c| c != dependency);
+            nodes.get_mut(&dependency).unwrap().parent = old_parent;
+            nodes.get_mut(&old_parent).unwrap().children.push(dependency);
+        }
+
+        nodes.get_mut(&old_parent).unwrap().children.retain(|&c| c != stream_id);
+
+        if exclusive {
+            let siblings: Vec<u32> = std::mem::take(&mut nodes.get_mut(&dependency).unwrap().children);
+            for sibling in &siblings {
+                nodes.get_mut(sibling).unwrap().parent = stream_id;
+            }
+            nodes.get_mut(&stream_id).unwrap().children.extend(siblings);
+        }
+
+        {
+            let node = nodes.get_mut(&stream_id).unwrap();
+            node.parent = dependency;
+            node.weight = weight;
+        }
+        nodes.get_mut(&dependency).unwrap().children.push(stream_id);
+
+        Ok(())
+    }
+
+    /// Picks the next stream that should be allowed to emit DATA, given the
+    /// set of streams that currently have some buffered (`ready`). Starting
+    /// at the root, each level distributes its turn among sibling nodes in
+    /// proportion to their weight, only descending into a node's children
+    /// once that node itself has nothing ready to send. Returns `None` if no
+    /// stream in `ready` is reachable from the tree (in particular, if
+    /// `ready` is empty).
+    pub fn next_to_send(&self, ready: &HashSet<u32>) -> Option<u32> {
+        let mut nodes = self.nodes.lock().unwrap();
+        // a stream with buffered DATA but no PRIORITY/HEADERS-priority ever
+        // seen for it hasn't been registered by `apply`; give it the default
+        // dependency-on-root treatment here so it's still reachable from the
+        // tree walk below.
+        for &stream_id in ready {
+            ensure_node(&mut nodes, stream_id);
+        }
+        pick(&mut nodes, 0, ready)
+    }
+}
+
+/// Whether `id` depends, directly or transitively, on `ancestor` — i.e.
+/// walking `id`'s parent chain reaches `ancestor` before the root.
+fn is_descendant(nodes: &HashMap<u32, Node>, ancestor: u32, id: u32) -> bool {
+    let mut cur = id;
+    while cur != 0 {
+        let parent = nodes[&cur].parent;
+        if parent == ancestor {
+            return true;
+        }
+        cur = parent;
+    }
+    false
+}
+
+fn ensure_node(nodes: &mut HashMap<u32, Node>, stream_id: u32) {
+    if stream_id == 0 || nodes.contains_key(&stream_id) {
+        return;
+    }
+    nodes.insert(stream_id, Node::new(0, DEFAULT_WEIGHT));
+    nodes.get_mut(&0).unwrap().children.push(stream_id);
+}
+
+fn has_sendable_descendant(nodes: &HashMap<u32, Node>, id: u32, ready: &HashSet<u32>) -> bool {
+    ready.contains(&id)
+        || nodes[&id].children.iter().any(|c| has_sendable_descendant(nodes, *c, ready))
+}
+
+fn pick(nodes: &mut HashMap<u32, Node>, parent: u32, ready: &HashSet<u32>) -> Option<u32> {
+    let children = nodes[&parent].children.clone();
+    let eligible: Vec<u32> = children.into_iter()
+        .filter(|c| has_sendable_descendant(nodes, *c, ready))
+        .collect();
+    if eligible.is_empty() {
+        return None;
+    }
+
+    let chosen = weighted_pick(nodes, &eligible);
+    if ready.contains(&chosen) {
+        Some(chosen)
+    } else {
+        // `chosen` has nothing of its own ready; its turn passes to whichever
+        // of its own children does.
+        pick(nodes, chosen, ready)
+    }
+}
+
+/// Nginx-style smooth weighted round-robin: bumps every eligible sibling's
+/// slider by its weight, picks the highest, then knocks that one back down
+/// by the eligible set's combined weight. Over repeated calls this
+/// interleaves siblings in proportion to weight instead of exhausting one
+/// sibling's whole share before moving to the next.
+fn weighted_pick(nodes: &mut HashMap<u32, Node>, eligible: &[u32]) -> u32 {
+    let total: i64 = eligible.iter().map(|id| nodes[id].weight as i64).sum();
+
+    let mut chosen = eligible[0];
+    let mut chosen_weight = i64::min_value();
+    for id in eligible {
+        let node = nodes.get_mut(id).unwrap();
+        node.current_weight += node.weight as i64;
+        if node.current_weight > chosen_weight {
+            chosen_weight = node.current_weight;
+            chosen = *id;
+        }
+    }
+    nodes.get_mut(&chosen).unwrap().current_weight -= total;
+    chosen
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_stream_defaults_to_depending_on_root_with_default_weight() {
+        let tree = PriorityTree::new();
+        let mut ready = HashSet::new();
+        ready.insert(1);
+        assert_eq!(tree.next_to_send(&ready), Some(1));
+    }
+
+    #[test]
+    fn self_dependency_is_a_protocol_error() {
+        let tree = PriorityTree::new();
+        let err = tree.apply(1, 1, false, 16).unwrap_err();
+        assert!(format!("{:?}", err).contains("ProtocolError"), "{:?}", err);
+    }
+
+    #[test]
+    fn dependency_on_an_unseen_stream_creates_an_idle_placeholder() {
+        let tree = PriorityTree::new();
+        // stream 5 depends on stream 3, which has never been mentioned.
+        tree.apply(5, 3, false, 16).unwrap();
+        let mut ready = HashSet::new();
+        ready.insert(5);
+        // 3 is idle (not in `ready`), so its turn passes straight to 5.
+        assert_eq!(tree.next_to_send(&ready), Some(5));
+    }
+
+    #[test]
+    fn a_ready_parent_is_preferred_over_its_ready_children() {
+        let tree = PriorityTree::new();
+        tree.apply(2, 1, false, 16).unwrap();
+        let mut ready = HashSet::new();
+        ready.insert(1);
+        ready.insert(2);
+        for _ in 0..10 {
+            assert_eq!(tree.next_to_send(&ready), Some(1));
+        }
+    }
+
+    #[test]
+    fn an_idle_parent_defers_to_its_ready_child() {
+        let tree = PriorityTree::new();
+        tree.apply(2, 1, false, 16).unwrap();
+        let mut ready = HashSet::new();
+        ready.insert(2);
+        assert_eq!(tree.next_to_send(&ready), Some(2));
+    }
+
+    #[test]
+    fn siblings_are_interleaved_in_proportion_to_weight() {
+        let tree = PriorityTree::new();
+        tree.apply(1, 0, false, 3).unwrap();
+        tree.apply(2, 0, false, 1).unwrap();
+        let mut ready = HashSet::new();
+        ready.insert(1);
+        ready.insert(2);
+
+        let mut counts = HashMap::new();
+        for _ in 0..40 {
+            let picked = tree.next_to_send(&ready).unwrap();
+            *counts.entry(picked).or_insert(0) += 1;
+        }
+        // stream 1's weight is 3x stream 2's, so it should get roughly 3x the turns.
+        let ratio = *counts.get(&1).unwrap() as f64 / *counts.get(&2).unwrap() as f64;
+        assert!((ratio - 3.0).abs() < 0.5, "{:?}", counts);
+    }
+
+    #[test]
+    fn exclusive_reparenting_moves_existing_children_under_the_new_stream() {
+        let tree = PriorityTree::new();
+        tree.apply(1, 0, false, 16).unwrap();
+        tree.apply(2, 0, false, 16).unwrap();
+        // stream 3 exclusively depends on the root: 1 and 2 (the root's
+        // existing children) move under 3.
+        tree.apply(3, 0, true, 16).unwrap();
+
+        let mut ready = HashSet::new();
+        ready.insert(1);
+        // with nothing of its own ready, 3's turn passes down to its new
+        // child 1, which proves the reparenting actually happened.
+        assert_eq!(tree.next_to_send(&ready), Some(1));
+    }
+
+    #[test]
+    fn reprioritizing_onto_a_descendant_does_not_orphan_the_cycle() {
+        let tree = PriorityTree::new();
+        tree.apply(1, 0, false, 16).unwrap(); // 1 depends on root
+        tree.apply(2, 1, false, 16).unwrap(); // 2 depends on 1
+        // 1 is reprioritized to depend on 2, one of its own dependencies:
+        // without cycle handling this detaches {1, 2} from the root
+        // entirely instead of moving 2 to 1's former parent (the root).
+        tree.apply(1, 2, false, 16).unwrap();
+
+        let mut ready = HashSet::new();
+        ready.insert(1);
+        ready.insert(2);
+        // 2 now hangs directly off the root (in 1's old place) and is
+        // ready, so it's picked without ever descending to 1.
+        assert_eq!(tree.next_to_send(&ready), Some(2));
+
+        ready.remove(&2);
+        // with 2 idle, its turn passes down to its child 1 -- proving 1
+        // is still reachable too, rather than stranded in an orphaned cycle.
+        assert_eq!(tree.next_to_send(&ready), Some(1));
+    }
+
+    #[test]
+    fn no_ready_stream_yields_none() {
+        let tree = PriorityTree::new();
+        tree.apply(1, 0, false, 16).unwrap();
+        assert_eq!(tree.next_to_send(&HashSet::new()), None);
+    }
+}
@@ -1,3 +1,5 @@
+use super::error;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SettingKey {
     HeaderTableSize,
@@ -76,4 +78,45 @@ impl Settings {
             SettingKey::MaxHeaderListSize => self.values[6] = value,
         }
     }
+
+    /// Like `set`, but validates `value` against the legal range RFC 7540 §6.5.2
+    /// defines for `key` first. Use this (rather than `set`) for values coming
+    /// from a peer's SETTINGS frame, so an out-of-range value is rejected with
+    /// the proper connection error instead of being stored blindly; `set`
+    /// remains for our own internal defaults, which are trusted by construction.
+    pub fn apply(&mut self, key: SettingKey, value: u32) -> Result<(), SettingsError> {
+        match key {
+            SettingKey::EnablePush if value != 0 && value != 1 => {
+                return Err(SettingsError{
+                    key, value, code: error::Code::ProtocolError});
+            },
+            SettingKey::InitialWindowSize if value > 0x7fff_ffff => {
+                return Err(SettingsError{
+                    key, value, code: error::Code::FlowControlError});
+            },
+            SettingKey::MaxFrameSize if value < 16384 || value > 16_777_215 => {
+                return Err(SettingsError{
+                    key, value, code: error::Code::ProtocolError});
+            },
+            _ => (),
+        }
+        self.set(key, value);
+        Ok(())
+    }
+}
+
+/// A peer-supplied SETTINGS value that falls outside the range RFC 7540
+/// §6.5.2 allows for `key`. Carries the error code the connection should be
+/// torn down with (e.g. via a GOAWAY frame), per RFC 7540 §7.
+#[derive(Debug)]
+pub struct SettingsError {
+    pub key: SettingKey,
+    pub value: u32,
+    pub code: error::Code,
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid value {} for setting {:?}", self.value, self.key)
+    }
 }